@@ -1,3 +1,20 @@
+mod abi;
+mod analysis;
 mod backend;
+mod comments;
+mod config;
+mod dependencies;
+mod diagnostics;
+mod documents;
+mod hover;
+mod imports;
+mod index;
+mod intervals;
+mod layout;
+mod line_index;
+mod overlay;
+mod project;
+mod suppression;
+mod visit;
 
 pub use backend::Backend;
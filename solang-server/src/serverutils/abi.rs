@@ -0,0 +1,17 @@
+use tiny_keccak::{Hasher, Keccak};
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut hash = [0u8; 32];
+
+    hasher.update(data);
+    hasher.finalize(&mut hash);
+
+    hash
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("0x{}", digits)
+}
@@ -0,0 +1,117 @@
+use dashmap::DashMap;
+use solang::sema::ast;
+use tower_lsp::lsp_types::Url;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::hover::HoverText;
+use super::imports::Import;
+use super::intervals::IntervalIndex;
+use super::line_index::LineIndex;
+use super::overlay::Overlay;
+
+// The result of analysing a document at a particular version, along with everything the
+// language features need to answer requests without resolving the file again.
+pub struct Analysis {
+    pub version: Option<i64>,
+    // The target the document was analysed for.
+    pub target: String,
+    // Analyses of the document for the other configured targets, which are only used for
+    // their diagnostics.
+    pub other_targets: Vec<Analysis>,
+    pub ns: ast::Namespace,
+    // Contents of each file in ns.files, in the same order.
+    pub files: Vec<String>,
+    // Where each file in ns.files is on disk. Imports are named in ns.files the way they
+    // were imported, which need not be a path.
+    pub paths: Vec<PathBuf>,
+    // Imports which could not be resolved, by the file number they are in.
+    pub unresolved_imports: Vec<(usize, Import)>,
+    pub line_indexes: Vec<LineIndex>,
+    // Hover messages for ranges of offsets in each file, by file number.
+    pub hovers: Vec<IntervalIndex<HoverText>>,
+}
+
+impl Analysis {
+    // Does this analysis include the given file, either as the document or as an import.
+    pub fn uses_file(&self, path: &Path) -> bool {
+        self.paths.iter().any(|file| file == path)
+    }
+
+    pub fn file_uri(&self, file_no: usize) -> Option<Url> {
+        Url::from_file_path(self.paths.get(file_no)?).ok()
+    }
+
+    // The file number of the given document in this analysis.
+    pub fn file_no(&self, uri: &Url) -> Option<usize> {
+        let path = Overlay::canonical(&uri.to_file_path().ok()?);
+
+        self.paths.iter().position(|file| *file == path)
+    }
+}
+
+impl fmt::Debug for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Analysis")
+            .field("version", &self.version)
+            .field("target", &self.target)
+            .field("files", &self.ns.files)
+            .finish()
+    }
+}
+
+// Why a document could not be analysed.
+#[derive(Debug)]
+pub enum AnalysisError {
+    // The uri does not refer to a file on the local file system.
+    NotAFile,
+    // Solang panicked while resolving the file; holds the panic message.
+    Panic(String),
+}
+
+// Analyses keyed by document uri. An entry is only handed out while it matches the version
+// of the document it was computed for.
+#[derive(Debug, Default)]
+pub struct AnalysisCache {
+    entries: DashMap<Url, Arc<Analysis>>,
+}
+
+impl AnalysisCache {
+    pub fn get(&self, uri: &Url, version: Option<i64>) -> Option<Arc<Analysis>> {
+        self.entries
+            .get(uri)
+            .filter(|analysis| analysis.version == version)
+            .map(|analysis| analysis.clone())
+    }
+
+    pub fn insert(&self, uri: Url, analysis: Arc<Analysis>) {
+        self.entries.insert(uri, analysis);
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    // The documents which have imports that could not be resolved.
+    pub fn with_unresolved_imports(&self) -> Vec<Url> {
+        self.entries
+            .iter()
+            .filter(|entry| !entry.value().unresolved_imports.is_empty())
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    // Drops the analysis of the document itself and of every document which imports it,
+    // since their results depend on its text too.
+    pub fn invalidate(&self, uri: &Url) {
+        self.entries.remove(uri);
+
+        if let Ok(path) = uri.to_file_path() {
+            let path = Overlay::canonical(&path);
+            self.entries
+                .retain(|_, analysis| !analysis.uses_file(&path));
+        }
+    }
+}
@@ -3,15 +3,23 @@ use serde_json::Value;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-use solang::file_cache::FileCache;
 use solang::parse_and_resolve;
 use solang::Target;
 
 use lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
 use solang::sema::*;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::any::Any;
+use std::collections::HashSet;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::{task, time};
 
 use solang::*;
 
@@ -21,857 +29,1476 @@ use solang::parser::pt;
 
 use solang::sema::ast::Expression::*;
 
-use solang::sema::tags::*;
-
 use solang::sema::builtin::get_prototype;
 
-#[derive(Debug, Default)]
+use super::abi;
+use super::analysis::{Analysis, AnalysisCache, AnalysisError};
+use super::config::{self, Settings};
+use super::dependencies::Dependencies;
+use super::diagnostics;
+use super::documents::DocumentStore;
+use super::hover::HoverText;
+use super::imports::{ImportResolver, Remapping};
+use super::index::{self, WorkspaceIndex};
+use super::intervals::IntervalIndex;
+use super::layout::{Layout, LAYOUT_FILES};
+use super::line_index::LineIndex;
+use super::overlay::Overlay;
+use super::project::{Project, PROJECT_FILES};
+use super::suppression::Suppressions;
+use super::visit::{self, Visitor};
+
+// Work done progress tokens have to be unique while they are in use.
+static NEXT_PROGRESS_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Default, Clone)]
 pub struct Backend {
-    state: Vec<usize>,
+    documents: Arc<DocumentStore>,
+    analyses: Arc<AnalysisCache>,
+    // Bumped every time a document is scheduled for analysis, so that a run which has been
+    // superseded by a later edit can tell and drop its results.
+    generations: Arc<DashMap<Url, u64>>,
+    // The imported files each document has published diagnostics to.
+    published: Arc<DashMap<Url, HashSet<Url>>>,
+    // The reverse of published: the documents which have published diagnostics to each
+    // imported file, so that a file is only cleared once none of them do any more.
+    publishers: Arc<DashMap<Url, HashSet<Url>>>,
+    settings: Arc<RwLock<Settings>>,
+    // Settings of each workspace folder, as the client resolves them for the files in it.
+    folder_settings: Arc<DashMap<PathBuf, Settings>>,
+    // Root directories of the workspace.
+    roots: Arc<RwLock<Vec<PathBuf>>>,
+    // The project which applies to the files in a directory, by directory.
+    projects: Arc<DashMap<PathBuf, Option<Arc<Project>>>>,
+    // Project files which could not be loaded and have not been reported yet.
+    project_errors: Arc<DashMap<PathBuf, String>>,
+    // The foundry or hardhat layout of each workspace root which has one.
+    layouts: Arc<DashMap<PathBuf, Arc<Layout>>>,
+    // Which files each analysed document includes, to find the documents to analyse again
+    // when a file changes on disk.
+    dependencies: Arc<Dependencies>,
+    // Symbols and references of every file resolved so far.
+    index: Arc<WorkspaceIndex>,
+    // Files which are not open but get diagnostics anyway, in workspace diagnostics mode.
+    workspace_files: Arc<RwLock<HashSet<Url>>>,
+    // Whether the client renders markdown in hovers, rather than only plain text.
+    markdown_hover: Arc<AtomicBool>,
 }
 
 impl Backend {
-    // Calculate the line and coloumn from the Loc offset recieved from the parser
-    // Do a linear search till the correct offset location is matched
-    fn file_offset_to_line_column(data: &str, loc: usize) -> (usize, usize) {
-        let mut line_no = 0;
-        let mut past_ch = 0;
-
-        for (ind, c) in data.char_indices() {
-            if c == '\n' {
-                if ind == loc {
-                    break;
-                } else {
-                    past_ch = ind + 1;
-                    line_no += 1;
-                }
-            }
-            if ind == loc {
-                break;
-            }
+    // Parses and resolves the file at the given path, reading open documents from the
+    // overlay rather than from disk, and builds the lookup tables for the language features.
+    // This does all the heavy lifting so it is meant to run on a blocking worker.
+    fn analyse(
+        path: &Path,
+        overlay: &Overlay,
+        version: Option<i64>,
+        target: (String, Target),
+        resolver: &ImportResolver,
+    ) -> Option<Analysis> {
+        let filename = path.to_str()?;
+
+        let mut filecache = overlay.file_cache();
+
+        if let Some(dir) = path.parent() {
+            filecache.add_import_path(dir.to_path_buf());
+        }
+
+        for import_path in &resolver.import_paths {
+            filecache.add_import_path(import_path.clone());
         }
 
-        (line_no, loc - past_ch)
+        let graph = resolver.populate(path, overlay, &mut filecache);
+
+        let ns = parse_and_resolve(filename, &mut filecache, target.1);
+
+        let files: Vec<String> = ns
+            .files
+            .iter()
+            .map(|file| filecache.get_file_contents(file.as_str()).to_string())
+            .collect();
+
+        let paths: Vec<PathBuf> = ns
+            .files
+            .iter()
+            .map(|file| {
+                graph
+                    .resolved
+                    .get(file)
+                    .cloned()
+                    .unwrap_or_else(|| PathBuf::from(file))
+            })
+            .collect();
+
+        let unresolved_imports = graph
+            .unresolved
+            .into_iter()
+            .filter_map(|(file, import)| {
+                let file_no = paths.iter().position(|path| *path == file)?;
+                Some((file_no, import))
+            })
+            .collect();
+
+        let line_indexes = files.iter().map(|file| LineIndex::new(file)).collect();
+
+        let mut lookup_tbls: Vec<Vec<(u64, u64, HoverText)>> = vec![Vec::new(); ns.files.len()];
+
+        Backend::traverse(&ns, &files, &mut lookup_tbls);
+
+        let hovers = lookup_tbls
+            .into_iter()
+            .map(|lookup_tbl| {
+                IntervalIndex::new(
+                    lookup_tbl
+                        .into_iter()
+                        .filter(|(_, _, msg)| !msg.is_empty())
+                        .collect(),
+                )
+            })
+            .collect();
+
+        Some(Analysis {
+            version,
+            target: target.0,
+            other_targets: Vec::new(),
+            ns,
+            files,
+            paths,
+            unresolved_imports,
+            line_indexes,
+            hovers,
+        })
     }
 
-    // Convert the diagnostic messages recieved from the solang to lsp diagnostics types.
-    // Returns a vector of diagnostic messages for the client.
-    fn convert_to_diagnostics(ns: ast::Namespace, filecache: &mut FileCache) -> Vec<Diagnostic> {
-        let mut diagnostics_vec: Vec<Diagnostic> = Vec::new();
+    // Analyses the current text of the document on a blocking worker. A panic inside solang
+    // is caught and returned as an error, so it only costs the analysis of this version.
+    async fn run_analysis(&self, uri: &Url) -> std::result::Result<Arc<Analysis>, AnalysisError> {
+        let path = uri
+            .to_file_path()
+            .map_err(|_| AnalysisError::NotAFile)
+            .map(|path| Overlay::canonical(&path))?;
+        let doc = self.documents.get(uri);
+        let version = doc.as_ref().map(|doc| doc.version);
+        let overlay = Overlay::new(&self.documents);
+
+        let text = match doc {
+            Some(doc) => Some(doc.text),
+            None => fs::read_to_string(&path).ok(),
+        };
+        let settings = self.settings_for(&path);
+        let targets = Backend::targets(&settings, text.as_deref());
+        let resolver = self.import_resolver(&path, &settings);
+
+        let res = task::spawn_blocking(move || {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut analyses = targets
+                    .into_iter()
+                    .map(|target| Backend::analyse(&path, &overlay, version, target, &resolver));
+
+                let mut analysis = analyses.next()??;
+                analysis.other_targets = analyses.collect::<Option<_>>()?;
+
+                Some(analysis)
+            }))
+        })
+        .await;
 
-        for diag in ns.diagnostics {
-            let pos = diag.pos.unwrap();
+        match res {
+            Ok(Ok(Some(analysis))) => Ok(Arc::new(analysis)),
+            Ok(Ok(None)) => Err(AnalysisError::NotAFile),
+            Ok(Err(payload)) => Err(AnalysisError::Panic(panic_message(payload))),
+            Err(err) => Err(AnalysisError::Panic(err.to_string())),
+        }
+    }
 
-            let diagnostic = &diag;
+    // Returns the analysis of the current version of the document, resolving the document
+    // only if there is no cached result for that version.
+    async fn analysis(&self, uri: &Url) -> Option<Arc<Analysis>> {
+        let version = self.documents.get(uri).map(|doc| doc.version);
 
-            let sev = match diagnostic.level {
-                ast::Level::Info => DiagnosticSeverity::Information,
-                ast::Level::Warning => DiagnosticSeverity::Warning,
-                ast::Level::Error => DiagnosticSeverity::Error,
-                ast::Level::Debug => continue,
-            };
+        if let Some(analysis) = self.analyses.get(uri, version) {
+            return Some(analysis);
+        }
 
-            let mut file_str = "".to_owned();
-            for fils in ns.files.iter() {
-                let file_cont = filecache.get_file_contents(fils.as_str());
-                file_str.push_str(file_cont.as_str());
-            }
+        let analysis = self.run_analysis(uri).await.ok()?;
 
-            let l1 = Backend::file_offset_to_line_column(&file_str, pos.1);
+        self.store_analysis(uri, &analysis);
 
-            let l2 = Backend::file_offset_to_line_column(&file_str, pos.2);
+        Some(analysis)
+    }
 
-            let p1 = Position::new(l1.0 as u64, l1.1 as u64);
+    // Keeps the results of analysing a document. Only open documents are cached, the
+    // dependencies and index entries are kept for any file.
+    fn store_analysis(&self, uri: &Url, analysis: &Arc<Analysis>) {
+        if self.documents.get(uri).is_some() {
+            self.analyses.insert(uri.clone(), analysis.clone());
+        }
 
-            let p2 = Position::new(l2.0 as u64, l2.1 as u64);
+        self.dependencies
+            .update(uri, analysis.paths.iter().cloned().collect());
 
-            let range = Range::new(p1, p2);
+        self.index.update(analysis);
+    }
 
-            let message_slice = &diag.message[..];
+    // The solidity files in a directory and below it which are not excluded.
+    fn solidity_files(&self, dir: &Path) -> Vec<PathBuf> {
+        let pattern = dir.join("**").join("*.sol");
 
-            diagnostics_vec.push(Diagnostic {
-                range,
-                message: message_slice.to_string(),
-                severity: Some(sev),
-                source: Some("solidity".to_string()),
-                code: None,
-                related_information: None,
-                tags: None,
-            });
-        }
+        let paths = match glob::glob(&pattern.to_string_lossy()) {
+            Ok(paths) => paths,
+            Err(_) => return Vec::new(),
+        };
 
-        diagnostics_vec
+        paths
+            .filter_map(|path| path.ok())
+            .map(|path| Overlay::canonical(&path))
+            .filter(|path| !self.settings_for(path).is_excluded(path))
+            .collect()
     }
 
-    // Constructs the function type message which is returned as a String
-    fn construct_fnc(fnc_ty: &pt::FunctionTy) -> String {
-        let msg;
-        match fnc_ty {
-            pt::FunctionTy::Constructor => {
-                msg = String::from("Constructor");
-            }
-            pt::FunctionTy::Function => {
-                msg = String::from("Function");
-            }
-            pt::FunctionTy::Fallback => {
-                msg = String::from("Fallback");
-            }
-            pt::FunctionTy::Receive => {
-                msg = String::from("Recieve");
-            }
-            pt::FunctionTy::Modifier => {
-                msg = String::from("Modifier");
-            }
-        }
-        msg
+    // Resolves the files in the background and adds them to the workspace index, so that
+    // workspace symbols and references cover files which were never opened. Files which
+    // are in the index already, because something imported them, are not resolved again.
+    fn start_indexing(&self, client: &Client, files: Vec<PathBuf>) {
+        let backend = self.clone();
+        let client = client.clone();
+
+        tokio::spawn(async move {
+            backend.index_files(&client, files).await;
+        });
     }
 
-    fn construct_builtins(
-        bltn: &sema::ast::Builtin,
-        ns: &ast::Namespace,
-        fnc_map: &HashMap<String, String>,
-    ) -> String {
-        let mut msg = "[built-in] ".to_string();
-        let prot = get_prototype(bltn.clone());
-
-        if let Some(protval) = prot {
-            match &protval.builtin {
-                _ => {
-                    for ret in protval.ret {
-                        msg = format!("{} {}", msg, Backend::construct_defs(ret, ns, fnc_map));
-                    }
-                    msg = format!("{} {} (", msg, protval.name);
-                    for arg in protval.args {
-                        msg = format!("{}{}", msg, Backend::construct_defs(arg, ns, fnc_map));
+    async fn index_files(&self, client: &Client, files: Vec<PathBuf>) {
+        let token = NumberOrString::String(format!(
+            "solang-index-{}",
+            NEXT_PROGRESS_TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Progress can only be reported with a token the client agreed to
+        let progress = client
+            .send_custom_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await
+            .is_ok();
+
+        let report = |value: WorkDoneProgress| {
+            if progress {
+                client.send_custom_notification::<notification::Progress>(ProgressParams {
+                    token: token.clone(),
+                    value: ProgressParamsValue::WorkDone(value),
+                });
+            }
+        };
+
+        report(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+            title: "Indexing".to_string(),
+            cancellable: Some(false),
+            message: Some(format!("0/{} files", files.len())),
+            percentage: None,
+        }));
+
+        for (done, path) in files.iter().enumerate() {
+            if let Ok(uri) = Url::from_file_path(path) {
+                let diagnose = !self.is_library_file(path)
+                    && self.diagnose_workspace_file(&uri, &self.settings_for(path))
+                    && self.documents.get(&uri).is_none();
+
+                if diagnose || !self.index.contains(path) {
+                    if let Ok(analysis) = self.run_analysis(&uri).await {
+                        if diagnose {
+                            self.store_analysis(&uri, &analysis);
+                            self.publish_diagnostics(client, &uri, &analysis);
+                        } else {
+                            self.index.update(&analysis);
+                        }
                     }
-                    msg = format!("{}): {}", msg, protval.doc.to_string());
                 }
             }
+
+            report(WorkDoneProgress::Report(WorkDoneProgressReport {
+                cancellable: Some(false),
+                message: Some(format!("{}/{} files", done + 1, files.len())),
+                percentage: None,
+            }));
+        }
+
+        report(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(format!("{} files", files.len())),
+        }));
+
+        client.log_message(
+            MessageType::Info,
+            format!(
+                "indexed {} files, {} files in the index",
+                files.len(),
+                self.index.len()
+            ),
+        );
+
+        let settings = self.settings.read().unwrap().clone();
+
+        if settings.workspace_diagnostics
+            && self.workspace_files.read().unwrap().len() >= settings.workspace_diagnostics_limit
+        {
+            client.log_message(
+                MessageType::Warning,
+                format!(
+                    "workspace diagnostics are limited to {} files, others only get them when opened",
+                    settings.workspace_diagnostics_limit
+                ),
+            );
         }
-        msg
     }
 
-    // Constructs lookup table(messages) for the given statement by traversing the
-    // statements and traversing inside the contents of the statements.
-    fn construct_stmt(
-        stmt: &Statement,
-        lookup_tbl: &mut Vec<(u64, u64, String)>,
-        symtab: &sema::symtable::Symtable,
-        fnc_map: &HashMap<String, String>,
-        ns: &ast::Namespace,
-    ) {
-        match stmt {
-            Statement::VariableDecl(_locs, _, _param, expr) => {
-                if let Some(exp) = expr {
-                    Backend::construct_expr(exp, lookup_tbl, symtab, fnc_map, ns);
+    // Whether a file gets diagnostics without being open. In workspace diagnostics mode
+    // files are admitted in the order they are indexed, until the limit is reached.
+    fn diagnose_workspace_file(&self, uri: &Url, settings: &Settings) -> bool {
+        if !settings.workspace_diagnostics {
+            return false;
+        }
+
+        let mut workspace_files = self.workspace_files.write().unwrap();
+
+        if workspace_files.contains(uri) {
+            return true;
+        }
+
+        if workspace_files.len() >= settings.workspace_diagnostics_limit {
+            return false;
+        }
+
+        workspace_files.insert(uri.clone());
+
+        true
+    }
+
+    // Whether a file belongs to a dependency rather than to the project: anything in a
+    // node_modules directory or in a library directory of the layout of its folder. These
+    // would use up the workspace diagnostics limit before the sources are reached.
+    fn is_library_file(&self, path: &Path) -> bool {
+        if path
+            .components()
+            .any(|component| component.as_os_str() == "node_modules")
+        {
+            return true;
+        }
+
+        self.root_of(path)
+            .and_then(|root| self.layouts.get(&root).map(|layout| layout.clone()))
+            .map_or(false, |layout| {
+                layout.libs.iter().any(|lib| path.starts_with(lib))
+            })
+    }
+
+    fn is_workspace_file(&self, uri: &Url) -> bool {
+        self.workspace_files.read().unwrap().contains(uri)
+    }
+
+    // Diagnoses the whole workspace again after a change to the settings, if workspace
+    // diagnostics mode is on. When it has just been turned off, the diagnostics of the
+    // files which are not open are cleared.
+    fn refresh_workspace_diagnostics(&self, client: &Client, was_diagnosing: bool) {
+        let diagnose = self.settings.read().unwrap().workspace_diagnostics;
+
+        if diagnose {
+            self.trim_workspace_files(client);
+
+            let roots: Vec<PathBuf> = self.roots.read().unwrap().clone();
+            let files = roots
+                .iter()
+                .flat_map(|root| self.solidity_files(root))
+                .collect();
+
+            self.start_indexing(client, files);
+        } else if was_diagnosing && !diagnose {
+            self.clear_workspace_diagnostics(client);
+        }
+    }
+
+    fn clear_workspace_diagnostics(&self, client: &Client) {
+        self.drop_workspace_files(client, |_| false);
+    }
+
+    // Drops the workspace files which no longer get diagnostics, because they are not in a
+    // workspace folder any more, are excluded or are libraries. Beyond the limit the files
+    // which sort last are dropped.
+    fn trim_workspace_files(&self, client: &Client) {
+        let limit = self.settings.read().unwrap().workspace_diagnostics_limit;
+
+        let mut kept: Vec<Url> = self
+            .workspace_files
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|uri| match uri.to_file_path() {
+                Ok(path) => {
+                    self.root_of(&path).is_some()
+                        && !self.settings_for(&path).is_excluded(&path)
+                        && !self.is_library_file(&path)
                 }
-                let mut msg = Backend::construct_defs(&_param.ty, ns, fnc_map);
-                msg = format!("{} {}", msg, _param.name);
-                lookup_tbl.push((_param.loc.1 as u64, _param.loc.2 as u64, msg));
+                Err(_) => false,
+            })
+            .cloned()
+            .collect();
+
+        kept.sort();
+        kept.truncate(limit);
+
+        let kept: HashSet<Url> = kept.into_iter().collect();
+
+        self.drop_workspace_files(client, |uri| kept.contains(uri));
+    }
+
+    // Stops diagnosing the workspace files which are not kept. Unless a file is open, its
+    // diagnostics are cleared, along with those it published to imported files which no
+    // other document publishes to, and it is no longer analysed when its imports change.
+    fn drop_workspace_files(&self, client: &Client, keep: impl Fn(&Url) -> bool) {
+        let dropped: Vec<Url> = {
+            let mut workspace_files = self.workspace_files.write().unwrap();
+            let dropped: Vec<Url> = workspace_files
+                .iter()
+                .filter(|uri| !keep(uri))
+                .cloned()
+                .collect();
+
+            for uri in &dropped {
+                workspace_files.remove(uri);
             }
-            Statement::If(_locs, _, expr, stat1, stat2) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                for st1 in stat1 {
-                    Backend::construct_stmt(st1, lookup_tbl, symtab, fnc_map, ns);
-                }
-                for st2 in stat2 {
-                    Backend::construct_stmt(st2, lookup_tbl, symtab, fnc_map, ns);
+
+            dropped
+        };
+
+        for uri in dropped {
+            if self.documents.get(&uri).is_none() {
+                self.dependencies.remove(&uri);
+                self.generations.remove(&uri);
+
+                if let Some((_, files)) = self.published.remove(&uri) {
+                    self.unpublish(client, &uri, files.into_iter().collect());
                 }
+
+                client.publish_diagnostics(uri, Vec::new(), None);
             }
-            Statement::While(_locs, _blval, expr, stat1) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                for st1 in stat1 {
-                    Backend::construct_stmt(st1, lookup_tbl, symtab, fnc_map, ns);
+        }
+    }
+
+    // The targets to analyse a file for: the one it names itself if any, else the configured
+    // set of targets, or else the configured target. Unknown names are skipped, and if none
+    // are left the file is analysed for ewasm.
+    fn targets(settings: &Settings, text: Option<&str>) -> Vec<(String, Target)> {
+        let names = match text.and_then(config::file_target) {
+            Some(name) => vec![name],
+            None if !settings.targets.is_empty() => settings.targets.clone(),
+            None => vec![settings.target.clone()],
+        };
+
+        let mut targets: Vec<(String, Target)> = Vec::new();
+
+        for name in names {
+            let name = name.to_lowercase();
+
+            if let Some(target) = config::parse_target(&name) {
+                if targets.iter().all(|(n, _)| *n != name) {
+                    targets.push((name, target));
                 }
             }
-            Statement::For {
-                loc: _,
-                reachable: _,
-                init,
-                cond,
-                next,
-                body,
-            } => {
-                if let Some(exp) = cond {
-                    Backend::construct_expr(exp, lookup_tbl, symtab, fnc_map, ns);
-                }
-                for stat in init {
-                    Backend::construct_stmt(stat, lookup_tbl, symtab, fnc_map, ns);
-                }
-                for stat in next {
-                    Backend::construct_stmt(stat, lookup_tbl, symtab, fnc_map, ns);
-                }
-                for stat in body {
-                    Backend::construct_stmt(stat, lookup_tbl, symtab, fnc_map, ns);
-                }
+        }
+
+        if targets.is_empty() {
+            targets.push(("ewasm".to_string(), Target::Ewasm));
+        }
+
+        targets
+    }
+
+    // The project configuration for a file: the closest project file above it, or else the
+    // one above a workspace root. Projects are cached until a project file changes.
+    fn project(&self, path: &Path) -> Option<Arc<Project>> {
+        let dir = path.parent()?;
+
+        if let Some(project) = self.projects.get(dir) {
+            return project.clone();
+        }
+
+        let found = Project::discover(dir)
+            .or_else(|| self.root_of(path).and_then(|root| Project::discover(&root)));
+
+        let project = match found {
+            Some(Ok(project)) => Some(Arc::new(project)),
+            Some(Err(err)) => {
+                self.project_errors.insert(dir.to_path_buf(), err);
+                None
             }
-            Statement::DoWhile(_locs, _blval, stat1, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                for st1 in stat1 {
-                    Backend::construct_stmt(st1, lookup_tbl, symtab, fnc_map, ns);
+            None => None,
+        };
+
+        self.projects.insert(dir.to_path_buf(), project.clone());
+
+        project
+    }
+
+    // The workspace root the file is in. With nested workspace folders, the innermost one.
+    fn root_of(&self, path: &Path) -> Option<PathBuf> {
+        self.roots
+            .read()
+            .unwrap()
+            .iter()
+            .map(|root| Overlay::canonical(root))
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+
+    // Asks the client for the settings of each workspace folder, since a folder may
+    // override the settings of the workspace. Clients which cannot tell leave every folder
+    // with the workspace settings.
+    async fn fetch_folder_settings(&self, client: &Client) {
+        let roots: Vec<(PathBuf, Url)> = self
+            .roots
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|root| {
+                Some((
+                    Overlay::canonical(root),
+                    Url::from_directory_path(root).ok()?,
+                ))
+            })
+            .collect();
+
+        let items = roots
+            .iter()
+            .map(|(_, uri)| ConfigurationItem {
+                scope_uri: Some(uri.clone()),
+                section: Some("solidity".to_string()),
+            })
+            .collect();
+
+        let res = client
+            .send_custom_request::<request::WorkspaceConfiguration>(ConfigurationParams { items })
+            .await;
+
+        self.folder_settings.clear();
+
+        if let Ok(values) = res {
+            for ((root, _), value) in roots.into_iter().zip(values) {
+                if let Some(settings) = Backend::parse_settings(client, &value) {
+                    self.folder_settings.insert(root, settings);
                 }
             }
-            Statement::Expression(_locs, _, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Statement::Delete(_locs, _typ, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Statement::Destructure(_locs, _vecdestrfield, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                for vecstr in _vecdestrfield {
-                    match vecstr {
-                        DestructureField::Expression(expr) => {
-                            Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                        }
-                        _ => continue,
-                    }
+        }
+    }
+
+    // Works out the layout of every workspace root again, and logs which one was picked.
+    fn detect_layouts(&self, client: &Client) {
+        self.layouts.clear();
+
+        let roots: Vec<PathBuf> = self
+            .roots
+            .read()
+            .unwrap()
+            .iter()
+            .map(|root| Overlay::canonical(root))
+            .collect();
+
+        for root in roots {
+            match Layout::detect(&root) {
+                Some(layout) => {
+                    client.log_message(MessageType::Info, format!("using {}", layout));
+                    self.layouts.insert(root, Arc::new(layout));
                 }
-            }
-            Statement::Continue(_locs) => {}
-            Statement::Break(_) => {}
-            Statement::Return(_locs, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
+                None => {
+                    client.log_message(
+                        MessageType::Info,
+                        format!("no foundry or hardhat project at ‘{}’", root.display()),
+                    );
                 }
             }
-            Statement::Emit {
-                loc,
-                event_no,
-                event_loc: _,
-                args,
-            } => {
-                let evntdcl = &ns.events[*event_no];
-
-                let tag_msg = render(&evntdcl.tags[..]);
+        }
+    }
 
-                let mut temp_tbl: Vec<(u64, u64, String)> = Vec::new();
-                let mut evnt_msg = format!("{} event {} (", tag_msg, evntdcl.name);
+    // The editor settings of the workspace folder of the file, with the layout of that
+    // folder and the configuration of the project of the file layered on top.
+    fn settings_for(&self, path: &Path) -> Settings {
+        let root = self.root_of(path);
+
+        let mut settings = match root
+            .as_ref()
+            .and_then(|root| self.folder_settings.get(root))
+        {
+            Some(settings) => settings.clone(),
+            None => self.settings.read().unwrap().clone(),
+        };
+
+        if let Some(layout) =
+            root.and_then(|root| self.layouts.get(&root).map(|layout| layout.clone()))
+        {
+            layout.apply(&mut settings);
+        }
 
-                for filds in &evntdcl.fields {
-                    Backend::construct_strct(&filds, &mut temp_tbl, ns);
-                }
-                for entries in temp_tbl {
-                    evnt_msg = format!("{} {}, \n\n", evnt_msg, entries.2);
-                }
+        if let Some(project) = self.project(path) {
+            project.apply(&mut settings);
+        }
 
-                evnt_msg = format!("{} )", evnt_msg);
-                lookup_tbl.push((
-                    loc.1 as u64,
-                    (loc.1 + ns.events[*event_no].name.len()) as u64,
-                    evnt_msg,
-                ));
+        settings
+    }
 
-                for arg in args {
-                    Backend::construct_expr(arg, lookup_tbl, symtab, fnc_map, ns);
+    // How the imports of a file are resolved. The root of its project, or else of the
+    // workspace folder it is in, is searched after the configured import paths, along with
+    // the node_modules and lib directories in it. Relative import paths and remappings from
+    // the editor settings are taken relative to that root as well.
+    fn import_resolver(&self, path: &Path, settings: &Settings) -> ImportResolver {
+        let root = match self.project(path) {
+            Some(project) => Some(project.root.clone()),
+            None => self.root_of(path),
+        };
+
+        let base = root
+            .clone()
+            .or_else(|| path.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+
+        let mut import_paths: Vec<PathBuf> = settings
+            .import_paths
+            .iter()
+            .map(|import_path| base.join(import_path))
+            .collect();
+
+        if let Some(root) = root {
+            for dir in [root.clone(), root.join("node_modules"), root.join("lib")] {
+                if dir.is_dir() && !import_paths.contains(&dir) {
+                    import_paths.push(dir);
                 }
             }
-            Statement::TryCatch {
-                loc: _,
-                reachable: _,
-                expr,
-                returns: _,
-                ok_stmt,
-                error,
-                catch_param: _,
-                catch_param_pos: _,
-                catch_stmt,
-            } => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-                for vecstmt in catch_stmt {
-                    Backend::construct_stmt(vecstmt, lookup_tbl, symtab, fnc_map, ns);
-                }
-                for vecstmt in ok_stmt {
-                    Backend::construct_stmt(vecstmt, lookup_tbl, symtab, fnc_map, ns);
-                }
-                if let Some(okstmt) = error {
-                    for stmts in &okstmt.2 {
-                        Backend::construct_stmt(&stmts, lookup_tbl, symtab, fnc_map, ns);
-                    }
-                }
+        }
+
+        let remappings = settings
+            .remappings
+            .iter()
+            .filter_map(|remapping| Remapping::parse(remapping, &base))
+            .collect();
+
+        ImportResolver {
+            import_paths,
+            remappings,
+        }
+    }
+
+    fn report_project_errors(&self, client: &Client) {
+        let dirs: Vec<PathBuf> = self
+            .project_errors
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        for dir in dirs {
+            if let Some((_, err)) = self.project_errors.remove(&dir) {
+                client.show_message(MessageType::Error, err);
             }
-            Statement::Underscore(_loc) => {}
         }
     }
 
-    // Constructs lookup table(messages) by traversing over the expressions and storing
-    // the respective expression type messages in the table.
-    fn construct_expr(
-        expr: &Expression,
-        lookup_tbl: &mut Vec<(u64, u64, String)>,
-        symtab: &sema::symtable::Symtable,
-        fnc_map: &HashMap<String, String>,
-        ns: &ast::Namespace,
-    ) {
-        match expr {
-            FunctionArg(locs, typ, _sample_sz) => {
-                let msg = Backend::construct_defs(typ, ns, fnc_map);
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+    fn reanalyse_open_documents(&self, client: &Client) {
+        self.analyses.clear();
+
+        for (uri, _) in self.documents.snapshot() {
+            self.schedule_diagnostics(client, uri, Duration::from_millis(0));
+        }
+    }
+
+    // Reads the settings the client sent, if any. Settings which cannot be read are reported
+    // rather than dropped silently, as a single invalid value would otherwise throw away all
+    // the others with it.
+    fn parse_settings(client: &Client, value: &Value) -> Option<Settings> {
+        if value.is_null() {
+            return None;
+        }
+
+        match Settings::from_value(value) {
+            Ok(settings) => Some(settings),
+            Err(err) => {
+                client.show_message(
+                    MessageType::Warning,
+                    format!("invalid solidity settings, they are ignored: {}", err),
+                );
+                None
             }
+        }
+    }
 
-            // Variable types expression
-            BoolLiteral(locs, vl) => {
-                let msg = format!("(bool) {}", vl);
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+    // Replaces the settings and analyses every open document again, since any setting may
+    // change the results.
+    fn update_settings(&self, client: &Client, settings: Settings) {
+        for target in std::iter::once(&settings.target).chain(&settings.targets) {
+            if config::parse_target(target).is_none() {
+                client.show_message(
+                    MessageType::Warning,
+                    format!(
+                        "unknown target ‘{}’, expected one of: {}",
+                        target,
+                        config::TARGETS.join(", ")
+                    ),
+                );
             }
-            BytesLiteral(locs, typ, _vec_lst) => {
-                let msg = format!("({})", typ.to_string(ns));
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+        }
+
+        for remapping in &settings.remappings {
+            if Remapping::parse(remapping, Path::new("")).is_none() {
+                client.show_message(
+                    MessageType::Warning,
+                    format!("invalid remapping ‘{}’, expected prefix=path", remapping),
+                );
             }
-            CodeLiteral(locs, _val, _) => {
-                let msg = format!("({})", _val);
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+        }
+
+        *self.settings.write().unwrap() = settings;
+
+        self.reanalyse_open_documents(client);
+    }
+
+    fn next_generation(&self, uri: &Url) -> u64 {
+        let mut generation = self.generations.entry(uri.clone()).or_insert(0);
+        *generation += 1;
+        *generation
+    }
+
+    fn is_current(&self, uri: &Url, generation: u64) -> bool {
+        self.generations
+            .get(uri)
+            .map_or(false, |current| *current == generation)
+    }
+
+    // Analyses the document in the background once it has been left alone for the given
+    // delay, then publishes the diagnostics for the version that was analysed. If the
+    // document is scheduled again in the meantime, this run is abandoned and its results
+    // are dropped.
+    fn schedule_diagnostics(&self, client: &Client, uri: Url, delay: Duration) {
+        let generation = self.next_generation(&uri);
+        let backend = self.clone();
+        let client = client.clone();
+
+        tokio::spawn(async move {
+            if delay > Duration::from_millis(0) {
+                time::delay_for(delay).await;
             }
-            NumberLiteral(locs, typ, _bgit) => {
-                let msg = format!("({})", typ.to_string(ns));
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+
+            if !backend.is_current(&uri, generation) {
+                return;
             }
-            StructLiteral(_locs, _typ, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
+
+            client.log_message(MessageType::Info, uri.as_str());
+
+            if let Ok(path) = uri.to_file_path() {
+                let excluded = backend.settings_for(&path).is_excluded(&path);
+
+                backend.report_project_errors(&client);
+
+                if excluded {
+                    client.publish_diagnostics(uri, Vec::new(), None);
+                    return;
                 }
             }
-            ArrayLiteral(_locs, _, _arr, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
-                }
+
+            let res = backend.run_analysis(&uri).await;
+
+            if !backend.is_current(&uri, generation) {
+                return;
             }
-            ConstArrayLiteral(_locs, _, _arr, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
+
+            match res {
+                Ok(analysis) => {
+                    backend.store_analysis(&uri, &analysis);
+
+                    backend.publish_diagnostics(&client, &uri, &analysis);
+                }
+                Err(AnalysisError::Panic(msg)) => {
+                    backend.publish_internal_error(&client, uri, &msg);
                 }
+                Err(AnalysisError::NotAFile) => {}
             }
+        });
+    }
 
-            // Arithmetic expression
-            Add(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Subtract(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Multiply(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            UDivide(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SDivide(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            UModulo(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SModulo(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Power(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
+    // Reports a failed analysis on the document itself, so the user can tell why the
+    // diagnostics stopped updating, and as a message so it does not go unnoticed.
+    fn publish_internal_error(&self, client: &Client, uri: Url, msg: &str) {
+        let message = format!("internal error while analysing this file: {}", msg);
 
-            // Bitwise expresion
-            BitwiseOr(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            BitwiseAnd(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            BitwiseXor(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ShiftLeft(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ShiftRight(_locs, _typ, expr1, expr2, _bl) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
+        client.show_message(MessageType::Error, &message);
 
-            // Variable expression
-            Variable(locs, typ, _val) => {
-                let msg = format!("({})", Backend::construct_defs(typ, ns, fnc_map));
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
-            }
-            ConstantVariable(locs, typ, _val1, _val2) => {
-                let msg = format!("constant ({})", Backend::construct_defs(typ, ns, fnc_map));
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
-            }
-            StorageVariable(locs, typ, _val1, _val2) => {
-                let msg = format!("({})", Backend::construct_defs(typ, ns, fnc_map));
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
-            }
+        let version = self.documents.get(&uri).map(|doc| doc.version);
 
-            // Load expression
-            Load(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            StorageLoad(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ZeroExt(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SignExt(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Trunc(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Cast(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            BytesCast(_loc, _typ1, _typ2, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-            }
+        let diagnostic = Diagnostic {
+            range: Range::default(),
+            message,
+            severity: Some(DiagnosticSeverity::Error),
+            source: Some("solidity".to_string()),
+            code: None,
+            related_information: None,
+            tags: None,
+        };
 
-            //Increment-Decrement expression
-            PreIncrement(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            PreDecrement(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            PostIncrement(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            PostDecrement(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Assign(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
+        client.publish_diagnostics(uri, vec![diagnostic], version);
+    }
 
-            // Compare expression
-            UMore(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ULess(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            UMoreEqual(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ULessEqual(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SMore(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SLess(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SMoreEqual(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            SLessEqual(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Equal(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            NotEqual(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
+    // Publishes the diagnostics of the analysis of a document to each file they belong to.
+    // Imported files which are open themselves are left to their own analysis. Files which
+    // had diagnostics from the previous analysis of this document, but are no longer part of
+    // it, are cleared unless another document still publishes to them.
+    fn publish_diagnostics(&self, client: &Client, uri: &Url, analysis: &Analysis) {
+        let root_no = analysis.file_no(uri);
+        let mut published = HashSet::new();
+        let settings = match uri.to_file_path() {
+            Ok(path) => self.settings_for(&path),
+            Err(_) => self.settings.read().unwrap().clone(),
+        };
+
+        for (file_no, d) in Backend::target_diagnostics(analysis, &settings)
+            .into_iter()
+            .enumerate()
+        {
+            if Some(file_no) == root_no {
+                client.publish_diagnostics(uri.clone(), d, analysis.version);
+                continue;
+            }
+
+            if let Some(file_uri) = analysis.file_uri(file_no) {
+                if self.documents.get(&file_uri).is_none() && !self.is_workspace_file(&file_uri) {
+                    published.insert(file_uri.clone());
+                    client.publish_diagnostics(file_uri, d, None);
+                }
             }
+        }
 
-            Not(_locs, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Complement(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            UnaryMinus(_locs, _typ, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
+        for file in &published {
+            self.publishers
+                .entry(file.clone())
+                .or_insert_with(HashSet::new)
+                .insert(uri.clone());
+        }
 
-            Ternary(_locs, _typ, expr1, expr2, expr3) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr3, lookup_tbl, symtab, fnc_map, ns);
-            }
+        if let Some(previous) = self.published.insert(uri.clone(), published.clone()) {
+            let stale = previous.difference(&published).cloned().collect();
 
-            ArraySubscript(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
+            self.unpublish(client, uri, stale);
+        }
+    }
 
-            StructMember(_locs, _typ, expr1, _val) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
+    // Withdraws a document from publishing diagnostics to the given imported files. A file
+    // which no document publishes to any more is cleared, unless it is open or a workspace
+    // file and so has diagnostics of its own.
+    fn unpublish(&self, client: &Client, uri: &Url, files: Vec<Url>) {
+        for file in files {
+            if let Some(mut publishers) = self.publishers.get_mut(&file) {
+                publishers.remove(uri);
             }
 
-            // Array operation expression
-            AllocDynamicArray(_locs, _typ, expr1, _valvec) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            DynamicArrayLength(_locs, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            DynamicArraySubscript(_locs, _typ, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            DynamicArrayPush(_locs, expr1, _typ, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            DynamicArrayPop(_locs, expr1, _typ) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            StorageBytesSubscript(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            StorageBytesPush(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-            }
-            StorageBytesPop(_locs, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-            }
-            StorageBytesLength(_locs, expr1) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
+            if self
+                .publishers
+                .remove_if(&file, |_, publishers| publishers.is_empty())
+                .is_some()
+                && self.documents.get(&file).is_none()
+                && !self.is_workspace_file(&file)
+            {
+                client.publish_diagnostics(file, Vec::new(), None);
             }
+        }
+    }
 
-            //String operations expression
-            StringCompare(_locs, _strloc1, _strloc2) => {
-                if let StringLocation::RunTime(expr1) = _strloc1 {
-                    Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                }
-                if let StringLocation::RunTime(expr2) = _strloc1 {
-                    Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
+    fn debounce(&self) -> Duration {
+        Duration::from_millis(self.settings.read().unwrap().debounce_ms)
+    }
+
+    // The diagnostics of a document analysed for several targets, by file number of the
+    // analysis of the first target. Diagnostics are merged, and those which only some of the
+    // targets give are labelled with those targets in their source. A suppression counts as
+    // used when it suppresses anything for any of the targets.
+    fn target_diagnostics(analysis: &Analysis, settings: &Settings) -> Vec<Vec<Diagnostic>> {
+        if analysis.other_targets.is_empty() {
+            return Backend::convert_to_diagnostics(analysis, settings);
+        }
+
+        let mut merged: Vec<Vec<(Diagnostic, Vec<&str>)>> =
+            vec![Vec::new(); analysis.ns.files.len()];
+
+        for target in std::iter::once(analysis).chain(&analysis.other_targets) {
+            for (file_no, diags) in Backend::convert_to_diagnostics(target, settings)
+                .into_iter()
+                .enumerate()
+            {
+                let file_no = match analysis
+                    .paths
+                    .iter()
+                    .position(|path| *path == target.paths[file_no])
+                {
+                    Some(file_no) => file_no,
+                    None => continue,
+                };
+
+                let name = target.target.as_str();
+
+                // A target can report the same diagnostic more than once, which must not
+                // make it count as another target
+                for diag in diags {
+                    match merged[file_no].iter_mut().find(|(d, _)| *d == diag) {
+                        Some((_, targets)) if !targets.contains(&name) => targets.push(name),
+                        Some(_) => {}
+                        None => merged[file_no].push((diag, vec![name])),
+                    }
                 }
             }
-            StringConcat(_locs, _typ, _strloc1, _strloc2) => {
-                if let StringLocation::RunTime(expr1) = _strloc1 {
-                    Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                }
-                if let StringLocation::RunTime(expr2) = _strloc1 {
-                    Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
-                }
+        }
+
+        let count = analysis.other_targets.len() + 1;
+
+        merged
+            .into_iter()
+            .map(|diags| {
+                diags
+                    .into_iter()
+                    .filter_map(|(mut diag, targets)| {
+                        if targets.len() < count {
+                            if diag.code
+                                == Some(NumberOrString::String("unused-suppression".to_string()))
+                            {
+                                return None;
+                            }
+
+                            diag.source = Some(format!("solidity ({})", targets.join(", ")));
+                        }
+
+                        Some(diag)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Convert the diagnostic messages recieved from the solang to lsp diagnostics types.
+    // Returns the diagnostic messages for each file in the namespace, by file number.
+    fn convert_to_diagnostics(analysis: &Analysis, settings: &Settings) -> Vec<Vec<Diagnostic>> {
+        let mut diagnostics_vec: Vec<Vec<Diagnostic>> = vec![Vec::new(); analysis.ns.files.len()];
+
+        let mut suppressions: Vec<Suppressions> = analysis
+            .files
+            .iter()
+            .zip(&analysis.line_indexes)
+            .map(|(file, line_index)| Suppressions::new(file, line_index))
+            .collect();
+
+        for diag in &analysis.ns.diagnostics {
+            // Messages without a location, or with one we cannot map, are reported at the
+            // start of the file which was analysed.
+            let pos = diag
+                .pos
+                .filter(|pos| pos.0 < analysis.line_indexes.len())
+                .unwrap_or(pt::Loc(0, 0, 0));
+
+            let diagnostic = &diag;
+
+            let code = diagnostics::code(diagnostic);
+
+            // Imports which could not be found are reported on the import path below
+            if code == "import-not-found"
+                && analysis
+                    .unresolved_imports
+                    .iter()
+                    .any(|(file_no, _)| *file_no == pos.0)
+            {
+                continue;
+            }
+
+            let sev = match settings.diagnostics.get(code) {
+                Some(severity) => match severity.severity() {
+                    Some(sev) => sev,
+                    None => continue,
+                },
+                None => match diagnostic.level {
+                    ast::Level::Info => DiagnosticSeverity::Information,
+                    ast::Level::Warning => DiagnosticSeverity::Warning,
+                    ast::Level::Error => DiagnosticSeverity::Error,
+                    ast::Level::Debug if settings.debug_hints => DiagnosticSeverity::Hint,
+                    ast::Level::Debug => continue,
+                },
+            };
+
+            let line_index = &analysis.line_indexes[pos.0];
+
+            if suppressions[pos.0].suppresses(line_index.line_col(pos.1).line, code) {
+                continue;
             }
 
-            Or(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
+            let range = Range::new(line_index.position(pos.1), line_index.position(pos.2));
+
+            let message_slice = &diag.message[..];
+
+            let related_information = diag
+                .notes
+                .iter()
+                .filter_map(|note| {
+                    let line_index = analysis.line_indexes.get(note.pos.0)?;
+
+                    Some(DiagnosticRelatedInformation {
+                        location: Location::new(
+                            analysis.file_uri(note.pos.0)?,
+                            Range::new(
+                                line_index.position(note.pos.1),
+                                line_index.position(note.pos.2),
+                            ),
+                        ),
+                        message: note.message.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            diagnostics_vec[pos.0].push(Diagnostic {
+                range,
+                message: message_slice.to_string(),
+                severity: Some(sev),
+                source: Some("solidity".to_string()),
+                code: Some(NumberOrString::String(code.to_string())),
+                related_information: if related_information.is_empty() {
+                    None
+                } else {
+                    Some(related_information)
+                },
+                tags: diagnostics::tags(code),
+            });
+        }
+
+        for (file_no, import) in &analysis.unresolved_imports {
+            let line_index = &analysis.line_indexes[*file_no];
+            let code = "import-not-found";
+
+            if suppressions[*file_no].suppresses(line_index.line_col(import.start).line, code) {
+                continue;
             }
-            And(_locs, expr1, expr2) => {
-                Backend::construct_expr(expr1, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(expr2, lookup_tbl, symtab, fnc_map, ns);
+
+            let sev = match settings.diagnostics.get(code) {
+                Some(severity) => match severity.severity() {
+                    Some(sev) => sev,
+                    None => continue,
+                },
+                None => DiagnosticSeverity::Error,
+            };
+
+            diagnostics_vec[*file_no].push(Diagnostic {
+                range: Range::new(
+                    line_index.position(import.start),
+                    line_index.position(import.end),
+                ),
+                message: format!("file not found ‘{}’", import.path),
+                severity: Some(sev),
+                source: Some("solidity".to_string()),
+                code: Some(NumberOrString::String(code.to_string())),
+                related_information: None,
+                tags: None,
+            });
+        }
+
+        for (file_no, file_suppressions) in suppressions.iter().enumerate() {
+            let line_index = &analysis.line_indexes[file_no];
+
+            for problem in file_suppressions.problems() {
+                let sev = match settings.diagnostics.get(problem.code) {
+                    Some(severity) => match severity.severity() {
+                        Some(sev) => sev,
+                        None => continue,
+                    },
+                    None => DiagnosticSeverity::Warning,
+                };
+
+                diagnostics_vec[file_no].push(Diagnostic {
+                    range: Range::new(
+                        line_index.position(problem.start),
+                        line_index.position(problem.end),
+                    ),
+                    message: problem.message,
+                    severity: Some(sev),
+                    source: Some("solidity".to_string()),
+                    code: Some(NumberOrString::String(problem.code.to_string())),
+                    related_information: None,
+                    tags: diagnostics::tags(problem.code),
+                });
             }
+        }
 
-            // Function call expression
-            InternalFunctionCall {
-                loc,
-                returns: _,
-                contract_no,
-                function_no,
-                signature: _,
-                args: _,
-            } => {
-                let fnc = &ns.contracts[*contract_no].functions[*function_no];
-                let msg_tg = render(&fnc.tags[..]);
+        diagnostics_vec
+    }
+
+    // Constructs the hover for a builtin from its prototype.
+    fn construct_builtins(bltn: &sema::ast::Builtin, ns: &ast::Namespace) -> HoverText {
+        let protval = match get_prototype(bltn.clone()) {
+            Some(protval) => protval,
+            None => return HoverText::default(),
+        };
+
+        let args = protval
+            .args
+            .iter()
+            .map(|arg| Backend::construct_defs(arg, ns))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let rets = protval
+            .ret
+            .iter()
+            .filter(|ret| **ret != sema::ast::Type::Void)
+            .map(|ret| Backend::construct_defs(ret, ns))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut code = format!("// built-in\n{}({})", protval.name, args);
+
+        if !rets.is_empty() {
+            code = format!("{} returns ({})", code, rets);
+        }
 
-                let fnc_msg_type = Backend::construct_fnc(&fnc.ty);
-                let mut param_msg = format!("{} \n\n {} {}(", msg_tg, fnc_msg_type, fnc.name);
+        HoverText {
+            notice: Some(protval.doc.to_string()),
+            ..HoverText::code(code)
+        }
+    }
+
+    // Constructs the header of a function, as it would be declared.
+    fn function_header(fnc: &Function, ns: &ast::Namespace) -> String {
+        let params = |params: &[Parameter]| {
+            params
+                .iter()
+                .map(|param| Backend::parameter(param, ns))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let mut header = match fnc.ty {
+            pt::FunctionTy::Constructor => "constructor".to_string(),
+            pt::FunctionTy::Fallback => "fallback".to_string(),
+            pt::FunctionTy::Receive => "receive".to_string(),
+            pt::FunctionTy::Modifier => format!("modifier {}", fnc.name),
+            pt::FunctionTy::Function => format!("function {}", fnc.name),
+        };
+
+        header = format!("{}({})", header, params(&fnc.params));
+
+        match fnc.ty {
+            pt::FunctionTy::Constructor | pt::FunctionTy::Modifier => {}
+            _ => header = format!("{} {}", header, fnc.visibility),
+        }
 
-                for parm in &fnc.params {
-                    let msg = format!(
-                        "{}:{}, \n\n",
-                        parm.name,
-                        Backend::construct_defs(&parm.ty, ns, fnc_map)
-                    );
-                    param_msg = format!("{} {}", param_msg, msg);
-                }
+        if let Some(mutability) = &fnc.mutability {
+            header = format!("{} {}", header, mutability);
+        }
 
-                param_msg = format!("{} ) returns (", param_msg);
+        if fnc.is_virtual {
+            header = format!("{} virtual", header);
+        }
 
-                for ret in &fnc.returns {
-                    let msg = format!(
-                        "{}:{}, ",
-                        ret.name,
-                        Backend::construct_defs(&ret.ty, ns, fnc_map)
-                    );
-                    param_msg = format!("{} {}", param_msg, msg);
-                }
+        if let Some((_, bases)) = &fnc.is_override {
+            if bases.len() > 1 {
+                let bases = bases
+                    .iter()
+                    .map(|contract_no| ns.contracts[*contract_no].name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ");
 
-                param_msg = format!("{})", param_msg);
-                lookup_tbl.push((loc.1 as u64, loc.2 as u64, param_msg));
+                header = format!("{} override({})", header, bases);
+            } else {
+                header = format!("{} override", header);
             }
-            ExternalFunctionCall {
-                loc,
-                returns: _,
+        }
+
+        for modifier in &fnc.modifiers {
+            if let InternalFunctionCall {
                 contract_no,
                 function_no,
-                address,
                 args,
-                value,
-                gas,
-            } => {
-                let fnc = &ns.contracts[*contract_no].functions[*function_no];
-                let msg_tg = render(&fnc.tags[..]);
-                let fnc_msg_type = Backend::construct_fnc(&fnc.ty);
-                let mut param_msg = format!("{} \n\n {} {}(", msg_tg, fnc_msg_type, fnc.name);
-
-                for parm in &fnc.params {
-                    let msg = format!(
-                        "{}:{}, \n\n",
-                        parm.name,
-                        Backend::construct_defs(&parm.ty, ns, fnc_map)
-                    );
-                    param_msg = format!("{} {}", param_msg, msg);
+                ..
+            } = modifier
+            {
+                let name = &ns.contracts[*contract_no].functions[*function_no].name;
+
+                if args.is_empty() {
+                    header = format!("{} {}", header, name);
+                } else {
+                    header = format!("{} {}(...)", header, name);
                 }
+            }
+        }
 
-                param_msg = format!("{} ) \n\n returns (", param_msg);
+        if !fnc.returns.is_empty() {
+            header = format!("{} returns ({})", header, params(&fnc.returns));
+        }
 
-                for ret in &fnc.returns {
-                    let msg = format!(
-                        "{}:{}, ",
-                        ret.name,
-                        Backend::construct_defs(&ret.ty, ns, fnc_map)
-                    );
-                    param_msg = format!("{} {}", param_msg, msg);
-                }
+        header
+    }
 
-                param_msg = format!("{})", param_msg);
-                lookup_tbl.push((loc.1 as u64, loc.2 as u64, param_msg));
+    // Constructs the hover for a contract: its header and NatSpec, and the ERC-165
+    // identifier of an interface. Inherited functions are not part of the identifier.
+    fn contract_hover(contrct: &Contract, ns: &ast::Namespace) -> HoverText {
+        let mut hover =
+            HoverText::code(Backend::contract_header(contrct, ns)).docs(&contrct.tags, &[], &[]);
+
+        if matches!(contrct.ty, pt::ContractTy::Interface(_)) {
+            let id = contrct
+                .functions
+                .iter()
+                .filter(|fnc| matches!(fnc.ty, pt::FunctionTy::Function))
+                .fold(0, |id, fnc| id ^ fnc.selector());
+
+            hover
+                .identifiers
+                .push(("Interface ID".to_string(), abi::hex(&id.to_le_bytes())));
+        }
 
-                Backend::construct_expr(address, lookup_tbl, symtab, fnc_map, ns);
-                for expp in args {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
-                }
+        hover
+    }
 
-                Backend::construct_expr(value, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(gas, lookup_tbl, symtab, fnc_map, ns);
-            }
-            ExternalFunctionCallRaw {
-                loc: _,
-                ty: _,
-                address,
-                args,
-                value,
-                gas,
-            } => {
-                Backend::construct_expr(args, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(address, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(value, lookup_tbl, symtab, fnc_map, ns);
-                Backend::construct_expr(gas, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Constructor {
-                loc: _,
-                contract_no: _,
-                constructor_no: _,
-                args,
-                gas,
-                value,
-                salt,
-            } => {
-                Backend::construct_expr(gas, lookup_tbl, symtab, fnc_map, ns);
-                for expp in args {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
-                }
-                if let Some(optval) = value {
-                    Backend::construct_expr(optval, lookup_tbl, symtab, fnc_map, ns);
-                }
-                if let Some(optsalt) = salt {
-                    Backend::construct_expr(optsalt, lookup_tbl, symtab, fnc_map, ns);
-                }
-            }
+    // Constructs the header of a contract, interface or library with the contracts it
+    // inherits from.
+    fn contract_header(contrct: &Contract, ns: &ast::Namespace) -> String {
+        let mut header = format!("{} {}", contrct.ty, contrct.name);
 
-            //Hash table operation expression
-            Keccak256(_locs, _typ, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
-                }
-                lookup_tbl.push((
-                    _locs.1 as u64,
-                    _locs.2 as u64,
-                    String::from("Keccak256 hash"),
-                ));
-            }
+        if !contrct.bases.is_empty() {
+            let bases = contrct
+                .bases
+                .iter()
+                .map(|base| ns.contracts[base.contract_no].name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ");
 
-            ReturnData(locs) => {
-                let msg = String::from("Return");
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
-            }
-            GetAddress(locs, _typ) => {
-                let msg = String::from("Get address");
-                lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
-            }
-            Balance(_locs, _typ, expr) => {
-                Backend::construct_expr(expr, lookup_tbl, symtab, fnc_map, ns);
-            }
-            Builtin(_locs, _typ, _builtin, expr) => {
-                let msg = Backend::construct_builtins(_builtin, ns, fnc_map);
-                lookup_tbl.push((_locs.1 as u64, _locs.2 as u64, msg));
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
-                }
-            }
-            List(_locs, expr) => {
-                for expp in expr {
-                    Backend::construct_expr(expp, lookup_tbl, symtab, fnc_map, ns);
+            header = format!("{} is {}", header, bases);
+        }
+
+        header
+    }
+
+    // Constructs the hover for a function: its header and NatSpec, and the selector it is
+    // called by if it can be called from outside the contract.
+    fn function_hover(fnc: &Function, ns: &ast::Namespace) -> HoverText {
+        let mut hover = HoverText::code(Backend::function_header(fnc, ns)).docs(
+            &fnc.tags,
+            &fnc.params,
+            &fnc.returns,
+        );
+
+        if Backend::has_selector(fnc, ns) {
+            // solang holds the first four bytes of the hash as a little endian number
+            let selector = abi::hex(&fnc.selector().to_le_bytes());
+
+            hover
+                .identifiers
+                .push(("Signature".to_string(), fnc.signature.clone()));
+            hover.identifiers.push(("Selector".to_string(), selector));
+        }
+
+        hover
+    }
+
+    // Whether a function is dispatched by selector: public and external functions, and on
+    // Substrate constructors as well, since a contract there can have several of them and
+    // picks one by the selector it is instantiated with.
+    fn has_selector(fnc: &Function, ns: &ast::Namespace) -> bool {
+        match fnc.ty {
+            pt::FunctionTy::Function => matches!(
+                fnc.visibility,
+                pt::Visibility::Public(_) | pt::Visibility::External(_)
+            ),
+            pt::FunctionTy::Constructor => matches!(ns.target, Target::Substrate),
+            _ => false,
+        }
+    }
+
+    // Constructs the hover for an event: its declaration and NatSpec, and the topic it is
+    // logged with. On Substrate the topics of an event are not derived from its signature.
+    fn event_hover(evntdcl: &EventDecl, ns: &ast::Namespace) -> HoverText {
+        let fields = evntdcl
+            .fields
+            .iter()
+            .map(|field| {
+                if field.indexed {
+                    format!("{} indexed {}", field.ty.to_string(ns), field.name)
+                } else {
+                    Backend::parameter(field, ns)
                 }
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut code = format!("event {}({})", evntdcl.name, fields);
+
+        if evntdcl.anonymous {
+            code = format!("{} anonymous", code);
+        }
+
+        let mut hover = HoverText::code(code).docs(&evntdcl.tags, &evntdcl.fields, &[]);
+
+        // An anonymous event has no topic for its signature
+        if !evntdcl.anonymous {
+            hover
+                .identifiers
+                .push(("Signature".to_string(), evntdcl.signature.clone()));
+
+            if !matches!(ns.target, Target::Substrate) {
+                let topic = abi::hex(&abi::keccak256(evntdcl.signature.as_bytes()));
+
+                hover.identifiers.push(("Topic".to_string(), topic));
             }
-            Poison => {}
+        }
+
+        hover
+    }
+
+    // A parameter, struct field or event field as "type name", or just the type when it
+    // has no name.
+    fn parameter(param: &Parameter, ns: &ast::Namespace) -> String {
+        let ty = param.ty.to_string(ns);
+
+        if param.name.is_empty() {
+            ty
+        } else {
+            format!("{} {}", ty, param.name)
         }
     }
 
-    // Constructs contract fields and stores it in the lookup table.
+    // Constructs contract fields and stores it in the lookup table, for the whole declaration
+    // as well as its name.
     fn construct_cont(
         contvar: &ContractVariable,
-        lookup_tbl: &mut Vec<(u64, u64, String)>,
-        samptb: &sema::symtable::Symtable,
-        fnc_map: &HashMap<String, String>,
+        text: &str,
+        lookup_tbl: &mut Vec<(u64, u64, HoverText)>,
         ns: &ast::Namespace,
     ) {
-        let msg_typ = Backend::construct_defs(&contvar.ty, ns, fnc_map);
-        let msg = format!("{} {}", msg_typ, contvar.name);
+        let msg_typ = Backend::construct_defs(&contvar.ty, ns);
+        let msg =
+            HoverText::code(format!("{} {}", msg_typ, contvar.name)).docs(&contvar.tags, &[], &[]);
+        let (start, end) = index::name_range(text, &contvar.loc, &contvar.name);
+        lookup_tbl.push((start as u64, end as u64, msg.clone()));
         lookup_tbl.push((contvar.loc.1 as u64, contvar.loc.2 as u64, msg));
         if let Some(expr) = &contvar.initializer {
-            Backend::construct_expr(&expr, lookup_tbl, samptb, fnc_map, ns);
+            let mut hovers = Hovers { ns, lookup_tbl };
+
+            visit::walk_expr(&mut hovers, expr);
         }
     }
 
     // Constructs struct fields and stores it in the lookup table.
     fn construct_strct(
         strfld: &Parameter,
-        lookup_tbl: &mut Vec<(u64, u64, String)>,
+        lookup_tbl: &mut Vec<(u64, u64, HoverText)>,
         ns: &ast::Namespace,
     ) {
-        let msg_typ = &strfld.ty.to_string(ns);
-        let msg = format!("{} {}", msg_typ, strfld.name);
+        let msg = HoverText::code(Backend::parameter(strfld, ns));
         lookup_tbl.push((strfld.loc.1 as u64, strfld.loc.2 as u64, msg));
     }
 
     // Traverses namespace to build messages stored in the lookup table for hover feature.
+    // Each file has a table of its own, by file number. The names of declarations are
+    // located in the text of the files.
     fn traverse(
         ns: &ast::Namespace,
-        lookup_tbl: &mut Vec<(u64, u64, String)>,
-        fnc_map: &mut HashMap<String, String>,
+        files: &[String],
+        lookup_tbls: &mut [Vec<(u64, u64, HoverText)>],
     ) {
-        for enm in &ns.enums {
+        for (enum_no, enm) in ns.enums.iter().enumerate() {
+            let lookup_tbl = &mut lookup_tbls[enm.loc.0];
+
             for (nam, vals) in &enm.values {
-                let evnt_msg = format!("{} {}, \n\n", nam, vals.1);
+                let evnt_msg = HoverText::code(format!("{}.{} = {}", enm.name, nam, vals.1));
                 lookup_tbl.push((vals.0 .1 as u64, vals.0 .2 as u64, evnt_msg));
             }
 
-            let msg_tg = render(&enm.tags[..]);
+            let (start, end) = index::name_range(&files[enm.loc.0], &enm.loc, &enm.name);
             lookup_tbl.push((
-                enm.loc.1 as u64,
-                (enm.loc.1 + enm.name.len()) as u64,
-                msg_tg,
+                start as u64,
+                end as u64,
+                Backend::type_hover(&sema::ast::Type::Enum(enum_no), ns),
             ));
         }
 
-        for strct in &ns.structs {
+        for (struct_no, strct) in ns.structs.iter().enumerate() {
+            let lookup_tbl = &mut lookup_tbls[strct.loc.0];
+
             for filds in &strct.fields {
                 Backend::construct_strct(&filds, lookup_tbl, ns);
             }
 
-            let msg_tg = render(&strct.tags[..]);
+            let (start, end) = index::name_range(&files[strct.loc.0], &strct.loc, &strct.name);
             lookup_tbl.push((
-                strct.loc.1 as u64,
-                (strct.loc.1 + strct.name.len()) as u64,
-                msg_tg,
+                start as u64,
+                end as u64,
+                Backend::type_hover(&sema::ast::Type::Struct(struct_no), ns),
             ));
         }
 
         for contrct in &ns.contracts {
-            let msg_tg = render(&contrct.tags[..]);
+            let lookup_tbl = &mut lookup_tbls[contrct.loc.0];
+
+            let (start, end) =
+                index::name_range(&files[contrct.loc.0], &contrct.loc, &contrct.name);
             lookup_tbl.push((
-                contrct.loc.1 as u64,
-                (contrct.loc.1 + msg_tg.len()) as u64,
-                msg_tg,
+                start as u64,
+                end as u64,
+                Backend::contract_hover(contrct, ns),
             ));
 
             for fnc in &contrct.functions {
-                for parm in &fnc.params {
-                    let msg = Backend::construct_defs(&parm.ty, ns, fnc_map);
+                let lookup_tbl = &mut lookup_tbls[fnc.loc.0];
+
+                let name = index::function_name(fnc);
+
+                let (start, end) = index::name_range(&files[fnc.loc.0], &fnc.loc, name);
+                lookup_tbl.push((start as u64, end as u64, Backend::function_hover(fnc, ns)));
+
+                for parm in fnc.params.iter().chain(&fnc.returns) {
+                    let msg = HoverText::code(Backend::parameter(parm, ns));
                     lookup_tbl.push((parm.loc.1 as u64, parm.loc.2 as u64, msg));
                 }
 
-                for ret in &fnc.returns {
-                    let msg = Backend::construct_defs(&ret.ty, ns, fnc_map);
-                    lookup_tbl.push((ret.loc.1 as u64, ret.loc.2 as u64, msg));
-                }
+                let mut hovers = Hovers { ns, lookup_tbl };
 
-                for stmt in &fnc.body {
-                    Backend::construct_stmt(&stmt, lookup_tbl, &fnc.symtable, fnc_map, ns);
-                }
+                visit::walk_stmts(&mut hovers, &fnc.body);
             }
 
             for varscont in &contrct.variables {
-                let samptb = symtable::Symtable::new();
-                Backend::construct_cont(varscont, lookup_tbl, &samptb, fnc_map, ns);
-
-                let msg_tg = render(&varscont.tags[..]);
-                lookup_tbl.push((
-                    varscont.loc.1 as u64,
-                    (varscont.loc.1 + varscont.name.len()) as u64,
-                    msg_tg,
-                ));
+                let lookup_tbl = &mut lookup_tbls[varscont.loc.0];
+
+                Backend::construct_cont(varscont, &files[varscont.loc.0], lookup_tbl, ns);
             }
         }
 
         for entdcl in &ns.events {
+            let lookup_tbl = &mut lookup_tbls[entdcl.loc.0];
+
             for filds in &entdcl.fields {
                 Backend::construct_strct(&filds, lookup_tbl, ns);
             }
-            let msg_tg = render(&entdcl.tags[..]);
-            lookup_tbl.push((
-                entdcl.loc.1 as u64,
-                (entdcl.loc.1 + entdcl.name.len()) as u64,
-                msg_tg,
-            ));
+
+            let (start, end) = index::name_range(&files[entdcl.loc.0], &entdcl.loc, &entdcl.name);
+            lookup_tbl.push((start as u64, end as u64, Backend::event_hover(entdcl, ns)));
         }
     }
 
-    fn construct_defs(
-        typ: &sema::ast::Type,
-        ns: &ast::Namespace,
-        _fnc_map: &HashMap<String, String>,
-    ) -> String {
+    fn construct_defs(typ: &sema::ast::Type, ns: &ast::Namespace) -> String {
         let def;
 
         match typ {
             sema::ast::Type::Ref(r) => {
-                def = Backend::construct_defs(r, ns, _fnc_map);
+                def = Backend::construct_defs(r, ns);
             }
             sema::ast::Type::StorageRef(r) => {
-                def = Backend::construct_defs(r, ns, _fnc_map);
+                def = Backend::construct_defs(r, ns);
             }
             sema::ast::Type::Mapping(k, v) => {
                 def = format!(
                     "mapping({} => {})",
-                    Backend::construct_defs(k, ns, _fnc_map),
-                    Backend::construct_defs(v, ns, _fnc_map)
+                    Backend::construct_defs(k, ns),
+                    Backend::construct_defs(v, ns)
                 );
             }
             sema::ast::Type::Array(ty, len) => {
                 def = format!(
                     "{}{}",
-                    Backend::construct_defs(ty, ns, _fnc_map),
+                    Backend::construct_defs(ty, ns),
                     len.iter()
                         .map(|l| match l {
                             None => "[]".to_string(),
@@ -881,92 +1508,205 @@ impl Backend {
                 );
             }
             sema::ast::Type::Struct(n) => {
-                let strct = &ns.structs[*n];
+                def = ns.structs[*n].name.clone();
+            }
+            sema::ast::Type::Enum(n) => {
+                def = ns.enums[*n].name.clone();
+            }
+            _ => {
+                def = typ.to_string(ns);
+            }
+        }
+
+        def
+    }
 
-                let tag_msg = render(&strct.tags[..]);
+    // Constructs the hover for a value of the given type. Structs and enums are shown with
+    // their definition and NatSpec.
+    fn type_hover(typ: &sema::ast::Type, ns: &ast::Namespace) -> HoverText {
+        match typ {
+            sema::ast::Type::Ref(r) | sema::ast::Type::StorageRef(r) => Backend::type_hover(r, ns),
+            sema::ast::Type::Struct(n) => {
+                let strct = &ns.structs[*n];
 
-                let mut temp_tbl: Vec<(u64, u64, String)> = Vec::new();
-                let mut evnt_msg = format!("{} struct {} `{{` \n\n", tag_msg, strct.name);
+                let mut code = format!("struct {} {{", strct.name);
 
                 for filds in &strct.fields {
-                    Backend::construct_strct(&filds, &mut temp_tbl, ns);
-                }
-                for entries in temp_tbl {
-                    evnt_msg = format!("{} {}, \n\n", evnt_msg, entries.2);
+                    code = format!("{}\n    {};", code, Backend::parameter(filds, ns));
                 }
 
-                evnt_msg = format!("{} \n\n`}}`", evnt_msg);
-
-                def = evnt_msg;
+                HoverText::code(format!("{}\n}}", code)).docs(&strct.tags, &[], &[])
             }
             sema::ast::Type::Enum(n) => {
                 let enm = &ns.enums[*n];
 
-                let tag_msg = render(&enm.tags[..]);
-
-                let mut evnt_msg = format!("{} enum {} `{{` \n\n", tag_msg, enm.name);
+                let mut values: Vec<(&String, usize)> =
+                    enm.values.iter().map(|(nam, vals)| (nam, vals.1)).collect();
+                values.sort_by_key(|(_, no)| *no);
 
-                for (nam, vals) in &enm.values {
-                    evnt_msg = format!("{} {} {}, \n\n", evnt_msg, nam, vals.1);
-                }
+                let values = values
+                    .iter()
+                    .map(|(nam, _)| format!("    {}", nam))
+                    .collect::<Vec<String>>()
+                    .join(",\n");
 
-                def = format!("{} \n\n`}}`", evnt_msg);
-            }
-            _ => {
-                def = typ.to_string(ns);
+                HoverText::code(format!("enum {} {{\n{}\n}}", enm.name, values)).docs(
+                    &enm.tags,
+                    &[],
+                    &[],
+                )
             }
+            _ => HoverText::code(Backend::construct_defs(typ, ns)),
         }
-
-        def
     }
+}
+
+// Builds the hover messages for the statements and expressions of a function body or of
+// the initializer of a state variable.
+struct Hovers<'a> {
+    ns: &'a ast::Namespace,
+    lookup_tbl: &'a mut Vec<(u64, u64, HoverText)>,
+}
+
+impl<'a> Visitor for Hovers<'a> {
+    fn stmt(&mut self, stmt: &Statement) {
+        let ns = self.ns;
 
-    // Converts line, char position in a respective file to a file offset position of the same file.
-    fn line_char_to_offset(ln: u64, chr: u64, data: &str) -> u64 {
-        let mut line_no = 0;
-        let mut past_ch = 0;
-        let mut ofst = 0;
-        for (_ind, c) in data.char_indices() {
-            if line_no == ln && chr == past_ch {
-                ofst = _ind;
-                break;
+        match stmt {
+            Statement::VariableDecl(_, _, param, _) => {
+                let msg = format!("{} {}", Backend::construct_defs(&param.ty, ns), param.name);
+                self.lookup_tbl.push((
+                    param.loc.1 as u64,
+                    param.loc.2 as u64,
+                    HoverText::code(msg),
+                ));
             }
-            if c == '\n' {
-                line_no += 1;
-                past_ch = 0;
-            } else {
-                past_ch += 1;
+            Statement::Emit {
+                event_no,
+                event_loc,
+                ..
+            } => {
+                let evntdcl = &ns.events[*event_no];
+
+                self.lookup_tbl.push((
+                    event_loc.1 as u64,
+                    event_loc.2 as u64,
+                    Backend::event_hover(evntdcl, ns),
+                ));
             }
+            _ => {}
         }
-        ofst as u64
     }
 
-    // Searches the respective hover message from lookup table for the given mouse pointer.
-    fn get_hover_msg(
-        offset: &u64,
-        mut lookup_tbl: Vec<(u64, u64, String)>,
-        _fnc_map: &HashMap<String, String>,
-    ) -> String {
-        let mut res = format!("");
-
-        let mut ind = 0;
-
-        lookup_tbl.sort_by_key(|k| k.0);
-
-        for x in 0..lookup_tbl.len() {
-            if lookup_tbl[x].0 <= *offset && *offset <= lookup_tbl[x].1 {
-                res = lookup_tbl[ind].2.to_string();
-                break;
+    fn expr(&mut self, expr: &Expression) {
+        let ns = self.ns;
+
+        let (locs, msg) = match expr {
+            FunctionArg(locs, typ, _)
+            | Variable(locs, typ, _)
+            | StorageVariable(locs, typ, _, _) => (locs, Backend::type_hover(typ, ns)),
+            BoolLiteral(locs, vl) => (locs, HoverText::code(format!("bool {}", vl))),
+            BytesLiteral(locs, typ, _) | NumberLiteral(locs, typ, _) => {
+                (locs, HoverText::code(typ.to_string(ns)))
+            }
+            CodeLiteral(locs, contract_no, _) => (
+                locs,
+                HoverText {
+                    notice: Some(format!(
+                        "Code of contract {}",
+                        ns.contracts[*contract_no].name
+                    )),
+                    ..HoverText::code("bytes")
+                },
+            ),
+            ConstantVariable(locs, typ, _, _) => (
+                locs,
+                HoverText::code(format!("{} constant", Backend::construct_defs(typ, ns))),
+            ),
+            InternalFunctionCall {
+                loc,
+                contract_no,
+                function_no,
+                ..
             }
-            ind += 1;
-        }
+            | ExternalFunctionCall {
+                loc,
+                contract_no,
+                function_no,
+                ..
+            } => {
+                let fnc = &ns.contracts[*contract_no].functions[*function_no];
 
-        res
+                (loc, Backend::function_hover(fnc, ns))
+            }
+            Keccak256(locs, _, _) => (
+                locs,
+                HoverText::code("// built-in\nkeccak256(bytes) returns (bytes32)"),
+            ),
+            ReturnData(locs) => (
+                locs,
+                HoverText {
+                    notice: Some(String::from("Data returned by the last external call")),
+                    ..HoverText::code("bytes")
+                },
+            ),
+            GetAddress(locs, _) => (locs, HoverText::code("address(this)")),
+            Builtin(locs, _, builtin, _) => (locs, Backend::construct_builtins(builtin, ns)),
+            _ => return,
+        };
+
+        self.lookup_tbl.push((locs.1 as u64, locs.2 as u64, msg));
+    }
+}
+
+// Extracts the message from a caught panic, which is a string unless the panic was raised
+// with some other value.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
     }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: &Client, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(
+        &self,
+        client: &Client,
+        params: InitializeParams,
+    ) -> Result<InitializeResult> {
+        {
+            let mut roots = self.roots.write().unwrap();
+
+            if let Some(folders) = &params.workspace_folders {
+                roots.extend(folders.iter().filter_map(|f| f.uri.to_file_path().ok()));
+            } else if let Some(root) = params.root_uri.as_ref().and_then(|u| u.to_file_path().ok())
+            {
+                roots.push(root);
+            }
+        }
+
+        let markdown_hover = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref())
+            .map_or(false, |formats| formats.contains(&MarkupKind::Markdown));
+
+        self.markdown_hover.store(markdown_hover, Ordering::Relaxed);
+
+        if let Some(settings) = params
+            .initialization_options
+            .as_ref()
+            .and_then(|value| Backend::parse_settings(client, value))
+        {
+            self.update_settings(client, settings);
+        }
+
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
@@ -986,6 +1726,7 @@ impl LanguageServer for Backend {
                 }),
                 document_highlight_provider: None,
                 workspace_symbol_provider: Some(true),
+                references_provider: Some(true),
                 execute_command_provider: Some(ExecuteCommandOptions {
                     commands: vec!["dummy.do_something".to_string()],
                     work_done_progress_options: Default::default(),
@@ -1005,6 +1746,41 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, client: &Client, _: InitializedParams) {
         client.log_message(MessageType::Info, "server initialized!");
+
+        self.detect_layouts(client);
+
+        self.fetch_folder_settings(client).await;
+
+        let roots: Vec<PathBuf> = self.roots.read().unwrap().clone();
+        let files = roots
+            .iter()
+            .flat_map(|root| self.solidity_files(root))
+            .collect();
+
+        self.start_indexing(client, files);
+
+        let watchers = PROJECT_FILES
+            .iter()
+            .chain(LAYOUT_FILES)
+            .chain(&["*.sol"])
+            .map(|name| FileSystemWatcher {
+                glob_pattern: format!("**/{}", name),
+                kind: None,
+            })
+            .collect();
+
+        let registration = Registration {
+            id: "solang-watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+
+        if let Err(err) = client.register_capability(vec![registration]).await {
+            client.log_message(MessageType::Warning, format!("cannot watch files: {}", err));
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -1014,126 +1790,257 @@ impl LanguageServer for Backend {
     async fn did_change_workspace_folders(
         &self,
         client: &Client,
-        _: DidChangeWorkspaceFoldersParams,
+        params: DidChangeWorkspaceFoldersParams,
     ) {
         client.log_message(MessageType::Info, "workspace folders changed!");
-    }
 
-    async fn did_change_configuration(&self, client: &Client, _: DidChangeConfigurationParams) {
-        client.log_message(MessageType::Info, "configuration changed!");
-    }
+        let paths = |folders: &[WorkspaceFolder]| -> Vec<PathBuf> {
+            folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect()
+        };
 
-    async fn did_change_watched_files(&self, client: &Client, _: DidChangeWatchedFilesParams) {
-        client.log_message(MessageType::Info, "watched files have changed!");
-    }
+        let removed = paths(&params.event.removed);
+        let added = paths(&params.event.added);
 
-    async fn execute_command(
-        &self,
-        client: &Client,
-        _: ExecuteCommandParams,
-    ) -> Result<Option<Value>> {
-        client.log_message(MessageType::Info, "command executed!");
-        Ok(None)
-    }
+        let remaining: Vec<PathBuf> = {
+            let mut roots = self.roots.write().unwrap();
 
-    async fn did_open(&self, client: &Client, params: DidOpenTextDocumentParams) {
-        client.log_message(MessageType::Info, "file opened!");
+            roots.retain(|root| !removed.contains(root));
 
-        let uri = params.text_document.uri;
+            for root in &added {
+                if !roots.contains(root) {
+                    roots.push(root.clone());
+                }
+            }
 
-        if let Ok(path) = uri.to_file_path() {
-            let mut filecache = FileCache::new();
+            roots.iter().map(|root| Overlay::canonical(root)).collect()
+        };
 
-            let filecachepath = path.parent().unwrap();
+        // Files of a removed folder leave the index, unless they are in a folder nested
+        // inside it which is still open
+        for root in removed.iter().map(|root| Overlay::canonical(root)) {
+            if !remaining.iter().any(|other| other.starts_with(&root)) {
+                self.index.remove_dir(&root);
+            }
+        }
 
-            let tostrpath = filecachepath.to_str().unwrap();
+        self.projects.clear();
 
-            let mut p = PathBuf::new();
+        self.detect_layouts(client);
 
-            p.push(tostrpath.to_string());
+        self.fetch_folder_settings(client).await;
 
-            filecache.add_import_path(p);
+        // Files of a removed folder stop getting diagnostics, and the folders which are left
+        // may exclude more files than before
+        self.trim_workspace_files(client);
 
-            let uri_string = uri.to_string();
+        let files = added
+            .iter()
+            .flat_map(|root| self.solidity_files(root))
+            .collect();
 
-            client.log_message(MessageType::Info, &uri_string);
+        self.start_indexing(client, files);
+
+        self.reanalyse_open_documents(client);
+    }
 
-            let os_str = path.file_name().unwrap();
+    async fn did_change_configuration(
+        &self,
+        client: &Client,
+        params: DidChangeConfigurationParams,
+    ) {
+        client.log_message(MessageType::Info, "configuration changed!");
 
-            let ns = parse_and_resolve(os_str.to_str().unwrap(), &mut filecache, Target::Ewasm);
+        let was_diagnosing = self.settings.read().unwrap().workspace_diagnostics;
 
-            let d = Backend::convert_to_diagnostics(ns, &mut filecache);
+        self.fetch_folder_settings(client).await;
 
-            client.publish_diagnostics(uri, d, None);
+        if let Some(settings) = Backend::parse_settings(client, &params.settings) {
+            self.update_settings(client, settings);
+        } else {
+            self.reanalyse_open_documents(client);
         }
+
+        self.refresh_workspace_diagnostics(client, was_diagnosing);
     }
 
-    async fn did_change(&self, client: &Client, params: DidChangeTextDocumentParams) {
-        client.log_message(MessageType::Info, "file changed!");
+    async fn did_change_watched_files(&self, client: &Client, params: DidChangeWatchedFilesParams) {
+        client.log_message(MessageType::Info, "watched files have changed!");
 
-        let uri = params.text_document.uri;
+        let project_changed = params.changes.iter().any(|change| {
+            change
+                .uri
+                .to_file_path()
+                .map_or(false, |path| Project::is_project_file(&path))
+        });
+
+        let layout_changed = params.changes.iter().any(|change| {
+            change.uri.to_file_path().map_or(false, |path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map_or(false, |name| LAYOUT_FILES.contains(&name))
+            })
+        });
+
+        if layout_changed {
+            self.detect_layouts(client);
+        }
 
-        if let Ok(path) = uri.to_file_path() {
-            let mut filecache = FileCache::new();
+        if project_changed {
+            self.projects.clear();
+        }
+
+        if project_changed || layout_changed {
+            self.reanalyse_open_documents(client);
+
+            let diagnosing = self.settings.read().unwrap().workspace_diagnostics;
+            self.refresh_workspace_diagnostics(client, diagnosing);
+            return;
+        }
 
-            let filecachepath = path.parent().unwrap();
+        // Open documents are analysed with the text in the editor, so only changes to files
+        // which are not open matter
+        let mut dependents = HashSet::new();
+        let mut created = false;
+        let mut reindex = Vec::new();
 
-            let tostrpath = filecachepath.to_str().unwrap();
+        for change in &params.changes {
+            let path = match change.uri.to_file_path() {
+                Ok(path) => Overlay::canonical(&path),
+                Err(_) => continue,
+            };
 
-            let mut p = PathBuf::new();
+            if path.extension().map_or(true, |ext| ext != "sol")
+                || self.documents.get(&change.uri).is_some()
+            {
+                continue;
+            }
 
-            p.push(tostrpath.to_string());
+            dependents.extend(self.dependencies.dependents(&path));
 
-            filecache.add_import_path(p);
+            created |= change.typ == FileChangeType::Created;
 
-            let uri_string = uri.to_string();
+            self.index.remove(&path);
 
-            client.log_message(MessageType::Info, &uri_string);
+            if change.typ == FileChangeType::Deleted
+                && self.workspace_files.write().unwrap().remove(&change.uri)
+            {
+                self.dependencies.remove(&change.uri);
+                client.publish_diagnostics(change.uri.clone(), Vec::new(), None);
+            }
 
-            let os_str = path.file_name().unwrap();
+            if change.typ != FileChangeType::Deleted && !self.settings_for(&path).is_excluded(&path)
+            {
+                reindex.push(path);
+            }
+        }
 
-            let ns = parse_and_resolve(os_str.to_str().unwrap(), &mut filecache, Target::Ewasm);
+        if !reindex.is_empty() {
+            self.start_indexing(client, reindex);
+        }
 
-            let d = Backend::convert_to_diagnostics(ns, &mut filecache);
+        // A new file may be one an import could not find before
+        if created {
+            dependents.extend(self.analyses.with_unresolved_imports());
+        }
 
-            client.publish_diagnostics(uri, d, None);
+        for uri in dependents {
+            if self.documents.get(&uri).is_some() || self.is_workspace_file(&uri) {
+                self.analyses.invalidate(&uri);
+                self.schedule_diagnostics(client, uri, Duration::from_millis(0));
+            }
         }
     }
 
-    async fn did_save(&self, client: &Client, params: DidSaveTextDocumentParams) {
-        client.log_message(MessageType::Info, "file saved!");
+    async fn execute_command(
+        &self,
+        client: &Client,
+        _: ExecuteCommandParams,
+    ) -> Result<Option<Value>> {
+        client.log_message(MessageType::Info, "command executed!");
+        Ok(None)
+    }
+
+    async fn did_open(&self, client: &Client, params: DidOpenTextDocumentParams) {
+        client.log_message(MessageType::Info, "file opened!");
+
+        let doc = params.text_document;
+
+        self.documents.open(doc.uri.clone(), doc.text, doc.version);
+
+        self.analyses.invalidate(&doc.uri);
+
+        self.schedule_diagnostics(client, doc.uri, Duration::from_millis(0));
+    }
+
+    async fn did_change(&self, client: &Client, params: DidChangeTextDocumentParams) {
+        client.log_message(MessageType::Info, "file changed!");
 
         let uri = params.text_document.uri;
 
+        self.documents
+            .change(&uri, params.text_document.version, params.content_changes);
+
+        self.analyses.invalidate(&uri);
+
+        // Documents which import this one are analysed with its unsaved text as well
         if let Ok(path) = uri.to_file_path() {
-            let mut filecache = FileCache::new();
+            for dependent in self.dependencies.dependents(&Overlay::canonical(&path)) {
+                if dependent != uri
+                    && (self.documents.get(&dependent).is_some()
+                        || self.is_workspace_file(&dependent))
+                {
+                    self.analyses.invalidate(&dependent);
+                    self.schedule_diagnostics(client, dependent, self.debounce());
+                }
+            }
+        }
 
-            let filecachepath = path.parent().unwrap();
+        self.schedule_diagnostics(client, uri, self.debounce());
+    }
 
-            let tostrpath = filecachepath.to_str().unwrap();
+    async fn did_save(&self, client: &Client, params: DidSaveTextDocumentParams) {
+        client.log_message(MessageType::Info, "file saved!");
 
-            let mut p = PathBuf::new();
+        self.schedule_diagnostics(client, params.text_document.uri, Duration::from_millis(0));
+    }
 
-            p.push(tostrpath.to_string());
+    async fn did_close(&self, client: &Client, params: DidCloseTextDocumentParams) {
+        client.log_message(MessageType::Info, "file closed!");
 
-            filecache.add_import_path(p);
+        let uri = params.text_document.uri;
 
-            let uri_string = uri.to_string();
+        self.documents.close(&uri);
 
-            client.log_message(MessageType::Info, &uri_string);
+        self.analyses.invalidate(&uri);
 
-            let os_str = path.file_name().unwrap();
+        self.dependencies.remove(&uri);
 
-            let ns = parse_and_resolve(os_str.to_str().unwrap(), &mut filecache, Target::Ewasm);
+        self.generations.remove(&uri);
 
-            let d = Backend::convert_to_diagnostics(ns, &mut filecache);
+        if let Some((_, files)) = self.published.remove(&uri) {
+            self.unpublish(client, &uri, files.into_iter().collect());
+        }
 
-            client.publish_diagnostics(uri, d, None);
+        // In workspace diagnostics mode the file keeps its diagnostics, now from disk
+        if self.is_workspace_file(&uri) {
+            self.schedule_diagnostics(client, uri.clone(), Duration::from_millis(0));
+        } else {
+            client.publish_diagnostics(uri.clone(), Vec::new(), None);
         }
-    }
 
-    async fn did_close(&self, client: &Client, _: DidCloseTextDocumentParams) {
-        client.log_message(MessageType::Info, "file closed!");
+        // Documents which import this one go back to the file on disk, and publish their
+        // diagnostics to it now that it is no longer open
+        if let Ok(path) = uri.to_file_path() {
+            for dependent in self.dependencies.dependents(&Overlay::canonical(&path)) {
+                if self.documents.get(&dependent).is_some() || self.is_workspace_file(&dependent) {
+                    self.analyses.invalidate(&dependent);
+                    self.schedule_diagnostics(client, dependent, Duration::from_millis(0));
+                }
+            }
+        }
     }
 
     async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
@@ -1143,63 +2050,68 @@ impl LanguageServer for Backend {
         ])))
     }
 
-    async fn hover(&self, hverparam: HoverParams) -> Result<Option<Hover>> {
-        let txtdoc = hverparam.text_document_position_params.text_document;
-        let pos = hverparam.text_document_position_params.position;
-
-        let uri = txtdoc.uri;
-
-        if let Ok(path) = uri.to_file_path() {
-            let mut filecache = FileCache::new();
-
-            let filecachepath = path.parent().unwrap();
-
-            let tostrpath = filecachepath.to_str().unwrap();
-
-            let mut p = PathBuf::new();
-
-            p.push(tostrpath.to_string());
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        Ok(Some(self.index.symbols(&params.query)))
+    }
 
-            filecache.add_import_path(p);
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let uri = params.text_document_position.text_document.uri;
+        let pos = params.text_document_position.position;
 
-            let _uri_string = uri.to_string();
+        let path = match uri.to_file_path() {
+            Ok(path) => Overlay::canonical(&path),
+            Err(_) => return Ok(None),
+        };
 
-            let os_str = path.file_name().unwrap();
+        // Brings the index up to date with the document
+        if self.analysis(&uri).await.is_none() {
+            return Ok(None);
+        }
 
-            let ns = parse_and_resolve(os_str.to_str().unwrap(), &mut filecache, Target::Ewasm);
+        Ok(self.index.decl_at(&path, pos).map(|decl| {
+            self.index
+                .references(&decl, params.context.include_declaration)
+        }))
+    }
 
-            let mut lookup_tbl: Vec<(u64, u64, String)> = Vec::new();
-            let mut fnc_map: HashMap<String, String> = HashMap::new();
+    async fn hover(&self, hverparam: HoverParams) -> Result<Option<Hover>> {
+        let txtdoc = hverparam.text_document_position_params.text_document;
+        let pos = hverparam.text_document_position_params.position;
 
-            Backend::traverse(&ns, &mut lookup_tbl, &mut fnc_map);
+        let uri = txtdoc.uri;
 
-            let mut file_str = "".to_owned();
-            for fils in ns.files.iter() {
-                let file_cont = filecache.get_file_contents(fils.as_str());
-                file_str.push_str(file_cont.as_str());
-            }
+        let analysis = match self.analysis(&uri).await {
+            Some(analysis) => analysis,
+            None => return Ok(None),
+        };
 
-            let offst = Backend::line_char_to_offset(pos.line, pos.character, &file_str); // 0 based offset
+        let file_no = match analysis.file_no(&uri) {
+            Some(file_no) => file_no,
+            None => return Ok(None),
+        };
 
-            let msg = Backend::get_hover_msg(&offst, lookup_tbl, &fnc_map);
+        let line_index = &analysis.line_indexes[file_no];
 
-            let new_pos = (pos.line, pos.character);
+        let offst = line_index.position_to_offset(pos) as u64; // 0 based offset
 
-            let p1 = Position::new(pos.line as u64, pos.character as u64);
-            let p2 = Position::new(new_pos.0 as u64, new_pos.1 as u64);
-            let new_rng = Range::new(p1, p2);
+        // The innermost item wins, so hovering an argument of a call shows the argument
+        let entry = analysis.hovers[file_no].find(offst);
 
-            Ok(Some(Hover {
-                contents: HoverContents::Scalar(MarkedString::String(msg)),
-                range: Some(new_rng),
-            }))
+        let kind = if self.markdown_hover.load(Ordering::Relaxed) {
+            MarkupKind::Markdown
         } else {
-            Ok(Some(Hover {
-                contents: HoverContents::Scalar(MarkedString::String(
-                    "Failed to render hover".to_string(),
-                )),
-                range: None,
-            }))
-        }
+            MarkupKind::PlainText
+        };
+
+        Ok(entry.map(|(start, end, msg)| Hover {
+            contents: HoverContents::Markup(msg.render(kind)),
+            range: Some(Range::new(
+                line_index.position(*start as usize),
+                line_index.position(*end as usize),
+            )),
+        }))
     }
 }
@@ -0,0 +1,51 @@
+// The rest of a // comment which starts with the given directive, such as the codes after
+// "solang-disable". The directive has to be followed by the end of the comment or a space.
+pub fn directive<'a>(comment: &'a str, name: &str) -> Option<&'a str> {
+    let rest = comment.trim_start_matches('/').trim().strip_prefix(name)?;
+
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+// Byte offsets of the start and end of every // comment in the text. String literals and
+// block comments are skipped, so that slashes inside them are not taken for a comment.
+pub fn line_comments(text: &str) -> Vec<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut comments = Vec::new();
+    let mut ind = 0;
+
+    while ind < bytes.len() {
+        match bytes[ind] {
+            b'"' | b'\'' => {
+                let quote = bytes[ind];
+                ind += 1;
+                while ind < bytes.len() && bytes[ind] != quote && bytes[ind] != b'\n' {
+                    if bytes[ind] == b'\\' {
+                        ind += 1;
+                    }
+                    ind += 1;
+                }
+                ind += 1;
+            }
+            b'/' if bytes.get(ind + 1) == Some(&b'*') => {
+                ind = match text[ind + 2..].find("*/") {
+                    Some(end) => ind + 2 + end + 2,
+                    None => bytes.len(),
+                };
+            }
+            b'/' if bytes.get(ind + 1) == Some(&b'/') => {
+                let end = text[ind..]
+                    .find(['\n', '\r'])
+                    .map_or(bytes.len(), |end| ind + end);
+                comments.push((ind, end));
+                ind = end;
+            }
+            _ => ind += 1,
+        }
+    }
+
+    comments
+}
@@ -0,0 +1,90 @@
+use serde::Deserialize;
+use serde_json::Value;
+use solang::Target;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::comments::{directive, line_comments};
+use super::diagnostics::SeverityOverride;
+
+// Names of the targets solang can compile for, as accepted by its --target option.
+pub const TARGETS: &[&str] = &["substrate", "ewasm", "sabre", "generic"];
+
+pub fn parse_target(name: &str) -> Option<Target> {
+    match name.to_lowercase().as_str() {
+        "substrate" => Some(Target::Substrate),
+        "ewasm" => Some(Target::Ewasm),
+        "sabre" => Some(Target::Sabre),
+        "generic" => Some(Target::Generic),
+        _ => None,
+    }
+}
+
+// The target a file asks to be analysed for with a "// solang-target <name>" comment.
+pub fn file_target(text: &str) -> Option<String> {
+    line_comments(text).into_iter().find_map(|(start, end)| {
+        directive(&text[start..end], "solang-target").map(|name| name.trim().to_string())
+    })
+}
+
+// Settings sent by the client, either as initialization options or as a configuration
+// change. Anything left out keeps its default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    // The target to analyse for, unless a file names its own.
+    pub target: String,
+    // Targets to analyse for at once. Diagnostics which only some of them give are labelled
+    // with those targets. Takes precedence over target when not empty.
+    pub targets: Vec<String>,
+    // Milliseconds to wait after the last change before a document is analysed again.
+    pub debounce_ms: u64,
+    // Severity of diagnostics by their code, overriding the one solang gave them.
+    pub diagnostics: HashMap<String, SeverityOverride>,
+    // Publish solang's debug messages as hints.
+    pub debug_hints: bool,
+    // Directories imports are looked up in, besides the directory of the importing file.
+    // Relative paths are taken relative to the workspace folder.
+    pub import_paths: Vec<PathBuf>,
+    // "prefix=path" remappings, applied to imports before they are looked up.
+    pub remappings: Vec<String>,
+    // Glob patterns of files which are not analysed.
+    pub exclude: Vec<String>,
+    // Publish diagnostics for every solidity file in the workspace, not only open ones.
+    pub workspace_diagnostics: bool,
+    // The most files diagnosed in workspace diagnostics mode.
+    pub workspace_diagnostics_limit: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            target: "ewasm".to_string(),
+            targets: Vec::new(),
+            debounce_ms: 300,
+            diagnostics: HashMap::new(),
+            debug_hints: true,
+            import_paths: Vec::new(),
+            remappings: Vec::new(),
+            exclude: Vec::new(),
+            workspace_diagnostics: false,
+            workspace_diagnostics_limit: 1000,
+        }
+    }
+}
+
+impl Settings {
+    // The settings may arrive on their own or nested under the "solidity" section.
+    pub fn from_value(value: &Value) -> Result<Settings, serde_json::Error> {
+        let value = value.get("solidity").unwrap_or(value);
+
+        serde_json::from_value(value.clone())
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|pattern| {
+            glob::Pattern::new(pattern).map_or(false, |pattern| pattern.matches_path(path))
+        })
+    }
+}
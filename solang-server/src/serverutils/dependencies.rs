@@ -0,0 +1,51 @@
+use dashmap::DashMap;
+use tower_lsp::lsp_types::Url;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// The files each analysed document includes, and the reverse: the documents which include
+// each file. The files of an analysis are everything solang resolved, so a document depends
+// on the files it imports transitively as well.
+#[derive(Debug, Default)]
+pub struct Dependencies {
+    files: DashMap<Url, HashSet<PathBuf>>,
+    dependents: DashMap<PathBuf, HashSet<Url>>,
+}
+
+impl Dependencies {
+    // Records the files of the latest analysis of a document, replacing the previous ones.
+    pub fn update(&self, uri: &Url, files: HashSet<PathBuf>) {
+        self.remove(uri);
+
+        for file in &files {
+            self.dependents
+                .entry(file.clone())
+                .or_insert_with(HashSet::new)
+                .insert(uri.clone());
+        }
+
+        self.files.insert(uri.clone(), files);
+    }
+
+    pub fn remove(&self, uri: &Url) {
+        if let Some((_, files)) = self.files.remove(uri) {
+            for file in files {
+                if let Some(mut dependents) = self.dependents.get_mut(&file) {
+                    dependents.remove(uri);
+                }
+
+                self.dependents
+                    .remove_if(&file, |_, dependents| dependents.is_empty());
+            }
+        }
+    }
+
+    // The documents which include the given file.
+    pub fn dependents(&self, path: &Path) -> Vec<Url> {
+        self.dependents
+            .get(path)
+            .map(|dependents| dependents.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
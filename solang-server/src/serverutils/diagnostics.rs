@@ -0,0 +1,106 @@
+use serde::Deserialize;
+use solang::sema::ast;
+use tower_lsp::lsp_types::{DiagnosticSeverity, DiagnosticTag};
+
+// Stable codes for the categories of messages solang emits, along with fragments of the
+// messages which belong to them. The first entry with a matching fragment wins, so more
+// specific categories come first.
+const CODES: &[(&str, &[&str])] = &[
+    ("import-not-found", &["file not found"]),
+    (
+        "syntax",
+        &[
+            "unrecognised token",
+            "unrecognised end of file",
+            "invalid token",
+        ],
+    ),
+    ("unknown-pragma", &["unknown pragma"]),
+    ("unreachable", &["unreachable"]),
+    ("unused-variable", &["never used", "never read", "unused"]),
+    ("deprecated", &["deprecated"]),
+    ("implicit-conversion", &["implicit conversion"]),
+    ("truncation", &["truncated", "too large", "does not fit"]),
+    (
+        "state-mutability",
+        &["can be declared", "reads from state", "writes to state"],
+    ),
+    ("shadowing", &["shadows"]),
+    (
+        "duplicate-definition",
+        &["already defined", "already declared", "duplicate"],
+    ),
+    ("override", &["override", "virtual"]),
+    ("visibility", &["visibility"]),
+    (
+        "undeclared",
+        &["not found", "not declared", "not defined", "unknown"],
+    ),
+    ("type-mismatch", &["expected", "mismatch", "conversion"]),
+];
+
+// Codes given to diagnostics about problems the server finds itself.
+const SERVER_CODES: &[&str] = &["unknown-suppression", "unused-suppression"];
+
+// Codes for messages which fit none of the categories.
+const LEVEL_CODES: &[&str] = &["error", "warning", "info", "debug"];
+
+pub fn is_known(code: &str) -> bool {
+    CODES.iter().any(|(c, _)| *c == code)
+        || SERVER_CODES.contains(&code)
+        || LEVEL_CODES.contains(&code)
+}
+
+// Code of the given solang diagnostic. Messages which fit none of the categories get a code
+// for their level, so every diagnostic has one.
+pub fn code(diag: &ast::Diagnostic) -> &'static str {
+    for (code, fragments) in CODES {
+        if fragments.iter().any(|f| diag.message.contains(f)) {
+            return code;
+        }
+    }
+
+    match diag.level {
+        ast::Level::Error => "error",
+        ast::Level::Warning => "warning",
+        ast::Level::Info => "info",
+        ast::Level::Debug => "debug",
+    }
+}
+
+// Tags for the diagnostics with the given code, so editors can grey out code which has no
+// effect and strike through deprecated constructs.
+pub fn tags(code: &str) -> Option<Vec<DiagnosticTag>> {
+    match code {
+        "unused-variable" | "unreachable" | "unused-suppression" => {
+            Some(vec![DiagnosticTag::Unnecessary])
+        }
+        "deprecated" => Some(vec![DiagnosticTag::Deprecated]),
+        _ => None,
+    }
+}
+
+// Severity a project wants for a diagnostic code, instead of the one solang gave it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeverityOverride {
+    Error,
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+    Off,
+}
+
+impl SeverityOverride {
+    // The severity to publish with, or None if the diagnostic should not be published.
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            SeverityOverride::Error => Some(DiagnosticSeverity::Error),
+            SeverityOverride::Warning => Some(DiagnosticSeverity::Warning),
+            SeverityOverride::Information => Some(DiagnosticSeverity::Information),
+            SeverityOverride::Hint => Some(DiagnosticSeverity::Hint),
+            SeverityOverride::Off => None,
+        }
+    }
+}
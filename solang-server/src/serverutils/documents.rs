@@ -0,0 +1,164 @@
+use dashmap::DashMap;
+use tower_lsp::lsp_types::{TextDocumentContentChangeEvent, Url};
+
+use super::line_index::LineIndex;
+
+// The editor's copy of an open document.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub text: String,
+    pub version: i64,
+}
+
+// Keeps the text of every open document, kept up to date from the incremental
+// changes sent by the client.
+#[derive(Debug, Default)]
+pub struct DocumentStore {
+    docs: DashMap<Url, Document>,
+}
+
+impl DocumentStore {
+    pub fn open(&self, uri: Url, text: String, version: i64) {
+        self.docs.insert(uri, Document { text, version });
+    }
+
+    pub fn close(&self, uri: &Url) {
+        self.docs.remove(uri);
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<Document> {
+        self.docs.get(uri).map(|doc| doc.clone())
+    }
+
+    pub fn snapshot(&self) -> Vec<(Url, Document)> {
+        self.docs
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    // Applies the content changes in the order they were sent. A change without a range
+    // replaces the whole document.
+    pub fn change(
+        &self,
+        uri: &Url,
+        version: Option<i64>,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) {
+        if let Some(mut doc) = self.docs.get_mut(uri) {
+            for change in changes {
+                match change.range {
+                    Some(range) => {
+                        let line_index = LineIndex::new(&doc.text);
+                        let start = line_index.position_to_offset(range.start);
+                        let end = line_index.position_to_offset(range.end).max(start);
+                        doc.text.replace_range(start..end, &change.text);
+                    }
+                    None => doc.text = change.text,
+                }
+            }
+            if let Some(version) = version {
+                doc.version = version;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    fn edit(start: (u64, u64), end: (u64, u64), text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn store(text: &str) -> (DocumentStore, Url) {
+        let store = DocumentStore::default();
+        let uri = Url::parse("file:///test.sol").unwrap();
+
+        store.open(uri.clone(), text.to_string(), 1);
+
+        (store, uri)
+    }
+
+    #[test]
+    fn range_edits() {
+        let (store, uri) = store("contract A {\n}\n");
+
+        store.change(&uri, Some(2), vec![edit((0, 9), (0, 10), "Token")]);
+        store.change(&uri, Some(3), vec![edit((1, 0), (1, 0), "    uint x;\n")]);
+
+        let doc = store.get(&uri).unwrap();
+
+        assert_eq!(doc.text, "contract Token {\n    uint x;\n}\n");
+        assert_eq!(doc.version, 3);
+    }
+
+    #[test]
+    fn several_changes() {
+        let (store, uri) = store("a\nb\nc\n");
+
+        // Each change applies to the text left by the one before it
+        store.change(
+            &uri,
+            Some(2),
+            vec![
+                edit((0, 0), (1, 0), ""),
+                edit((0, 0), (0, 1), "x"),
+                edit((1, 1), (1, 1), "y"),
+            ],
+        );
+
+        assert_eq!(store.get(&uri).unwrap().text, "x\ncy\n");
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let (store, uri) = store("s = \"\u{fc}\u{1f600}x\";\n");
+
+        // ü is one utf-16 code unit and the emoji two, so x starts at character 8
+        store.change(&uri, Some(2), vec![edit((0, 8), (0, 9), "y")]);
+
+        assert_eq!(store.get(&uri).unwrap().text, "s = \"\u{fc}\u{1f600}y\";\n");
+    }
+
+    #[test]
+    fn past_the_end() {
+        let (store, uri) = store("ab\ncd");
+
+        store.change(&uri, Some(2), vec![edit((1, 10), (5, 0), "e")]);
+        assert_eq!(store.get(&uri).unwrap().text, "ab\ncde");
+
+        // An end before the start is taken as an insertion
+        store.change(&uri, Some(3), vec![edit((0, 2), (0, 1), "!")]);
+        assert_eq!(store.get(&uri).unwrap().text, "ab!\ncde");
+    }
+
+    #[test]
+    fn full_replacement() {
+        let (store, uri) = store("old");
+
+        store.change(
+            &uri,
+            None,
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "new".to_string(),
+            }],
+        );
+
+        let doc = store.get(&uri).unwrap();
+
+        assert_eq!(doc.text, "new");
+        assert_eq!(doc.version, 1);
+    }
+}
@@ -0,0 +1,176 @@
+use solang::sema::ast::Parameter;
+use solang::sema::tags::Tag;
+use tower_lsp::lsp_types::{MarkupContent, MarkupKind};
+
+// What hovering an item shows: Solidity source for it, such as its type or the header of a
+// declaration, followed by its NatSpec documentation.
+#[derive(Debug, Default, Clone)]
+pub struct HoverText {
+    pub code: String,
+    // Values derived from the declaration, such as its selector, as (label, value).
+    pub identifiers: Vec<(String, String)>,
+    pub notice: Option<String>,
+    pub dev: Option<String>,
+    // Documented parameters and return values as (name, description). The name of a return
+    // value may be empty.
+    pub params: Vec<(String, String)>,
+    pub returns: Vec<(String, String)>,
+}
+
+impl HoverText {
+    pub fn code(code: impl Into<String>) -> Self {
+        HoverText {
+            code: code.into(),
+            ..HoverText::default()
+        }
+    }
+
+    // Adds the NatSpec of a declaration. @param and @return tags refer to the parameters
+    // and return values of the declaration by number.
+    pub fn docs(mut self, tags: &[Tag], params: &[Parameter], returns: &[Parameter]) -> Self {
+        for tag in tags {
+            let value = tag.value.trim().to_string();
+
+            match tag.tag.as_str() {
+                "notice" => self.notice = Some(value),
+                "dev" => self.dev = Some(value),
+                "param" => {
+                    if let Some(param) = params.get(tag.no) {
+                        self.params.push((param.name.clone(), value));
+                    }
+                }
+                "return" => {
+                    let name = returns
+                        .get(tag.no)
+                        .map(|ret| ret.name.clone())
+                        .unwrap_or_default();
+
+                    self.returns.push((name, value));
+                }
+                _ => {}
+            }
+        }
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+            && self.identifiers.is_empty()
+            && self.notice.is_none()
+            && self.dev.is_none()
+            && self.params.is_empty()
+            && self.returns.is_empty()
+    }
+
+    pub fn render(&self, kind: MarkupKind) -> MarkupContent {
+        let value = match kind {
+            MarkupKind::Markdown => self.markdown(),
+            MarkupKind::PlainText => self.plaintext(),
+        };
+
+        MarkupContent { kind, value }
+    }
+
+    fn markdown(&self) -> String {
+        let mut sections = Vec::new();
+
+        if !self.code.is_empty() {
+            sections.push(format!("```solidity\n{}\n```", self.code));
+        }
+
+        if !self.identifiers.is_empty() {
+            sections.push(
+                self.identifiers
+                    .iter()
+                    .map(|(label, value)| format!("{}: `{}`", label, value))
+                    .collect::<Vec<_>>()
+                    .join("  \n"),
+            );
+        }
+
+        sections.extend(self.notice.iter().cloned());
+        sections.extend(self.dev.iter().cloned());
+
+        if !self.params.is_empty() {
+            let mut table = String::from("| Parameter | Description |\n| --- | --- |");
+
+            for (name, desc) in &self.params {
+                table.push_str(&format!("\n| `{}` | {} |", name, table_cell(desc)));
+            }
+
+            sections.push(table);
+        }
+
+        if !self.returns.is_empty() {
+            let mut list = String::from("**Returns**\n");
+
+            for (name, desc) in &self.returns {
+                if name.is_empty() {
+                    list.push_str(&format!("\n- {}", desc));
+                } else {
+                    list.push_str(&format!("\n- `{}`: {}", name, desc));
+                }
+            }
+
+            sections.push(list);
+        }
+
+        sections.join("\n\n")
+    }
+
+    fn plaintext(&self) -> String {
+        let mut sections = Vec::new();
+
+        if !self.code.is_empty() {
+            sections.push(self.code.clone());
+        }
+
+        if !self.identifiers.is_empty() {
+            sections.push(
+                self.identifiers
+                    .iter()
+                    .map(|(label, value)| format!("{}: {}", label, value))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+
+        sections.extend(self.notice.iter().cloned());
+        sections.extend(self.dev.iter().cloned());
+
+        if !self.params.is_empty() {
+            let mut list = String::from("Parameters:");
+
+            for (name, desc) in &self.params {
+                list.push_str(&format!("\n  {}: {}", name, desc));
+            }
+
+            sections.push(list);
+        }
+
+        if !self.returns.is_empty() {
+            let mut list = String::from("Returns:");
+
+            for (name, desc) in &self.returns {
+                if name.is_empty() {
+                    list.push_str(&format!("\n  {}", desc));
+                } else {
+                    list.push_str(&format!("\n  {}: {}", name, desc));
+                }
+            }
+
+            sections.push(list);
+        }
+
+        sections.join("\n\n")
+    }
+}
+
+// A markdown table cell has to fit on one line and must not contain a bare pipe.
+fn table_cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
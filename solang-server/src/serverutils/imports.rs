@@ -0,0 +1,271 @@
+use solang::file_cache::FileCache;
+
+use std::collections::{HashMap, VecDeque};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use super::overlay::Overlay;
+
+// A "prefix=path" import remapping: imports starting with the prefix are looked up with the
+// prefix replaced by the path. A solc style context ("context:prefix=path") is accepted and
+// ignored.
+#[derive(Debug, Clone)]
+pub struct Remapping {
+    pub prefix: String,
+    pub target: PathBuf,
+}
+
+impl Remapping {
+    // Parses a remapping, with a relative target taken relative to the given directory.
+    pub fn parse(remapping: &str, base: &Path) -> Option<Remapping> {
+        let (prefix, target) = remapping.split_at(remapping.find('=')?);
+        let prefix = prefix.rsplit(':').next().unwrap_or(prefix).trim();
+        let target = target[1..].trim();
+
+        if prefix.is_empty() || target.is_empty() {
+            return None;
+        }
+
+        Some(Remapping {
+            prefix: prefix.to_string(),
+            target: base.join(target),
+        })
+    }
+}
+
+// An import directive: the path as written and the byte offsets of its string literal.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+// Finds the import directives in the source text. Comments are skipped, and the path is the
+// first string literal after the import keyword, which covers all forms of the directive.
+pub fn imports(text: &str) -> Vec<Import> {
+    let bytes = text.as_bytes();
+    let is_ident = |c: u8| c.is_ascii_alphanumeric() || c == b'_' || c == b'$';
+    let mut imports = Vec::new();
+    let mut in_import = false;
+    let mut ind = 0;
+
+    while ind < bytes.len() {
+        match bytes[ind] {
+            b'/' if bytes.get(ind + 1) == Some(&b'/') => {
+                ind = text[ind..].find('\n').map_or(bytes.len(), |end| ind + end);
+            }
+            b'/' if bytes.get(ind + 1) == Some(&b'*') => {
+                ind = text[ind + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| ind + 2 + end + 2);
+            }
+            b'"' | b'\'' => {
+                let quote = bytes[ind];
+                let start = ind;
+                ind += 1;
+                while ind < bytes.len() && bytes[ind] != quote && bytes[ind] != b'\n' {
+                    if bytes[ind] == b'\\' {
+                        ind += 1;
+                    }
+                    ind += 1;
+                }
+
+                // An unterminated literal ends at the end of the line, which is always on a
+                // character boundary
+                let end = ind.min(bytes.len());
+                ind = if bytes.get(end) == Some(&quote) {
+                    end + 1
+                } else {
+                    end
+                };
+
+                if in_import {
+                    imports.push(Import {
+                        path: text[start + 1..end].to_string(),
+                        start,
+                        end: ind,
+                    });
+                    in_import = false;
+                }
+            }
+            b';' => {
+                in_import = false;
+                ind += 1;
+            }
+            c if is_ident(c) => {
+                let start = ind;
+                while ind < bytes.len() && is_ident(bytes[ind]) {
+                    ind += 1;
+                }
+                if &text[start..ind] == "import" {
+                    in_import = true;
+                }
+            }
+            _ => ind += 1,
+        }
+    }
+
+    imports
+}
+
+// The files the imports of a document resolved to, and the ones which did not resolve.
+#[derive(Debug, Default)]
+pub struct ImportGraph {
+    // The file each import path resolved to.
+    pub resolved: HashMap<String, PathBuf>,
+    // Imports which could not be resolved, with the file they are in.
+    pub unresolved: Vec<(PathBuf, Import)>,
+}
+
+// Resolves import paths with remappings, the directory of the importing file and the
+// import paths, in that order.
+#[derive(Debug, Clone, Default)]
+pub struct ImportResolver {
+    pub import_paths: Vec<PathBuf>,
+    pub remappings: Vec<Remapping>,
+}
+
+impl ImportResolver {
+    pub fn resolve(&self, import: &str, dir: &Path) -> Option<PathBuf> {
+        let remapping = self
+            .remappings
+            .iter()
+            .filter(|remapping| import.starts_with(&remapping.prefix))
+            .max_by_key(|remapping| remapping.prefix.len());
+
+        if let Some(remapping) = remapping {
+            let path = remapping.target.join(&import[remapping.prefix.len()..]);
+
+            return path.canonicalize().ok();
+        }
+
+        if import.starts_with('.') {
+            return dir.join(import).canonicalize().ok();
+        }
+
+        iter::once(dir)
+            .chain(self.import_paths.iter().map(PathBuf::as_path))
+            .find_map(|import_path| import_path.join(import).canonicalize().ok())
+    }
+
+    // Follows the imports of the file and of everything it imports. Each imported file is
+    // added to the file cache under the path it is imported with, which is the name solang
+    // looks it up by, so that remapped imports resolve and open documents are read from the
+    // editor. The first file an import path resolves to wins.
+    pub fn populate(
+        &self,
+        root: &Path,
+        overlay: &Overlay,
+        filecache: &mut FileCache,
+    ) -> ImportGraph {
+        let mut graph = ImportGraph::default();
+        let mut queue = VecDeque::new();
+        let mut seen = vec![root.to_path_buf()];
+
+        queue.push_back(root.to_path_buf());
+
+        while let Some(file) = queue.pop_front() {
+            let text = match overlay.read(&file) {
+                Some(text) => text,
+                None => continue,
+            };
+
+            let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+
+            for import in imports(&text) {
+                if graph.resolved.contains_key(&import.path) {
+                    continue;
+                }
+
+                let path = match self.resolve(&import.path, &dir) {
+                    Some(path) => path,
+                    None => {
+                        graph.unresolved.push((file.clone(), import));
+                        continue;
+                    }
+                };
+
+                if let Some(contents) = overlay.read(&path) {
+                    filecache.set_file_contents(&import.path, contents);
+                }
+
+                graph.resolved.insert(import.path.clone(), path.clone());
+
+                if !seen.contains(&path) {
+                    seen.push(path.clone());
+                    queue.push_back(path);
+                }
+            }
+        }
+
+        graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(text: &str) -> Vec<String> {
+        imports(text)
+            .into_iter()
+            .map(|import| import.path)
+            .collect()
+    }
+
+    #[test]
+    fn remapping() {
+        let remapping =
+            Remapping::parse("@openzeppelin/=lib/openzeppelin/", Path::new("/ws")).unwrap();
+
+        assert_eq!(remapping.prefix, "@openzeppelin/");
+        assert_eq!(remapping.target, Path::new("/ws/lib/openzeppelin/"));
+
+        let remapping = Remapping::parse("ctx:ds-test/ = /abs/ds-test/", Path::new("/ws")).unwrap();
+
+        assert_eq!(remapping.prefix, "ds-test/");
+        assert_eq!(remapping.target, Path::new("/abs/ds-test/"));
+
+        assert!(Remapping::parse("no-equals-sign", Path::new("/ws")).is_none());
+        assert!(Remapping::parse("=lib/", Path::new("/ws")).is_none());
+        assert!(Remapping::parse("prefix/=", Path::new("/ws")).is_none());
+    }
+
+    #[test]
+    fn directives() {
+        let text = "import \"a.sol\";\n\
+                    import {A, B as C} from './b.sol';\n\
+                    import * as D from \"c.sol\";\n\
+                    import \"d.sol\" as E;\n";
+
+        assert_eq!(paths(text), vec!["a.sol", "./b.sol", "c.sol", "d.sol"]);
+
+        let import = &imports(text)[0];
+
+        assert_eq!(&text[import.start..import.end], "\"a.sol\"");
+    }
+
+    #[test]
+    fn not_imports() {
+        let text = "// import \"a.sol\";\n\
+                    /* import \"b.sol\"; */\n\
+                    string s = \"import\";\n\
+                    string t = \"c.sol\";\n\
+                    uint important = 1; string u = \"d.sol\";\n";
+
+        assert!(paths(text).is_empty());
+    }
+
+    #[test]
+    fn unterminated() {
+        assert_eq!(paths("import \""), vec![""]);
+        assert_eq!(paths("import \"a.sol"), vec!["a.sol"]);
+        assert_eq!(paths("import \"\u{fc}"), vec!["\u{fc}"]);
+        assert_eq!(paths("import \"a\\"), vec!["a\\"]);
+        assert_eq!(
+            paths("import \"a.sol\nimport \"b.sol\";"),
+            vec!["a.sol", "b.sol"]
+        );
+    }
+}
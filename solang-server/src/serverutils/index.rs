@@ -0,0 +1,438 @@
+use dashmap::DashMap;
+use solang::parser::pt;
+use solang::sema::ast::{Expression, Function, Statement};
+use tower_lsp::lsp_types::{Location, Position, Range, SymbolInformation, SymbolKind};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::analysis::Analysis;
+use super::visit::{self, Visitor};
+
+// A declaration, by the file it is in, the contract it is declared in, if any, and its
+// name. Functions and events go by their signature, so that overloads are told apart.
+// Unlike offsets this still holds after an edit elsewhere in the file, so the references
+// from files which have only been indexed, and are not analysed again, keep finding it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Decl {
+    pub path: PathBuf,
+    pub container: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    // The contract the symbol is declared in, if any.
+    pub container: Option<String>,
+    pub decl: Decl,
+    // Where the name of the declaration is.
+    pub location: Location,
+}
+
+// A use of a declaration.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub decl: Decl,
+    // Where the name is in the use, such as the function name of a call.
+    pub location: Location,
+}
+
+// The symbols declared in a file and the references made from it.
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    pub symbols: Vec<Symbol>,
+    pub references: Vec<Reference>,
+}
+
+// Symbols and references of every file which has been resolved so far, whether opened in
+// the editor, found by the indexer, or imported by either.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    files: DashMap<PathBuf, FileIndex>,
+}
+
+impl WorkspaceIndex {
+    // Replaces the entries of each file included in the analysis.
+    pub fn update(&self, analysis: &Analysis) {
+        for (path, file) in collect(analysis) {
+            self.files.insert(path, file);
+        }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    pub fn remove(&self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    // Drops every file under the given directory.
+    pub fn remove_dir(&self, dir: &Path) {
+        self.files.retain(|path, _| !path.starts_with(dir));
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    // Symbols whose name contains the query, ignoring case. An empty query matches all.
+    pub fn symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let query = query.to_lowercase();
+        let mut symbols = Vec::new();
+
+        for file in self.files.iter() {
+            for symbol in &file.symbols {
+                if symbol.name.to_lowercase().contains(&query) {
+                    symbols.push(SymbolInformation {
+                        name: symbol.name.clone(),
+                        kind: symbol.kind,
+                        deprecated: None,
+                        location: symbol.location.clone(),
+                        container_name: symbol.container.clone(),
+                    });
+                }
+            }
+        }
+
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+
+        symbols
+    }
+
+    // The declaration at the position in a file: the one a reference there refers to, or
+    // the one whose name is there.
+    pub fn decl_at(&self, path: &Path, pos: Position) -> Option<Decl> {
+        let file = self.files.get(path)?;
+
+        let reference = file
+            .references
+            .iter()
+            .filter(|r| contains(&r.location.range, pos))
+            .min_by_key(|r| span(&r.location.range));
+
+        if let Some(reference) = reference {
+            return Some(reference.decl.clone());
+        }
+
+        file.symbols
+            .iter()
+            .filter(|s| contains(&s.location.range, pos))
+            .min_by_key(|s| span(&s.location.range))
+            .map(|s| s.decl.clone())
+    }
+
+    // Every use of the declaration in the workspace, and the declaration itself if asked.
+    pub fn references(&self, decl: &Decl, include_decl: bool) -> Vec<Location> {
+        let mut locations = Vec::new();
+
+        for file in self.files.iter() {
+            if include_decl {
+                locations.extend(
+                    file.symbols
+                        .iter()
+                        .filter(|s| s.decl == *decl)
+                        .map(|s| s.location.clone()),
+                );
+            }
+
+            locations.extend(
+                file.references
+                    .iter()
+                    .filter(|r| r.decl == *decl)
+                    .map(|r| r.location.clone()),
+            );
+        }
+
+        locations
+    }
+}
+
+fn contains(range: &Range, pos: Position) -> bool {
+    let pos = (pos.line, pos.character);
+
+    (range.start.line, range.start.character) <= pos && pos <= (range.end.line, range.end.character)
+}
+
+// Size of a range, for picking the innermost one; lines count far more than columns.
+fn span(range: &Range) -> (u64, u64) {
+    (
+        range.end.line - range.start.line,
+        if range.end.line == range.start.line {
+            range.end.character - range.start.character
+        } else {
+            range.end.character
+        },
+    )
+}
+
+// Builds the symbols and references of every file in the analysis.
+fn collect(analysis: &Analysis) -> HashMap<PathBuf, FileIndex> {
+    let ns = &analysis.ns;
+    let mut files: HashMap<PathBuf, FileIndex> = analysis
+        .paths
+        .iter()
+        .map(|path| (path.clone(), FileIndex::default()))
+        .collect();
+
+    let mut add_symbol =
+        |name: &str, key: &str, kind, container: Option<&String>, loc: &pt::Loc| {
+            if let (Some(decl), Some(location)) = (
+                declaration(analysis, loc, container, key),
+                locate(analysis, loc, name),
+            ) {
+                if let Some(file) = files.get_mut(&decl.path) {
+                    file.symbols.push(Symbol {
+                        name: name.to_string(),
+                        kind,
+                        container: container.cloned(),
+                        decl,
+                        location,
+                    });
+                }
+            }
+        };
+
+    for contract in &ns.contracts {
+        add_symbol(
+            &contract.name,
+            &contract.name,
+            SymbolKind::Class,
+            None,
+            &contract.loc,
+        );
+
+        for fnc in &contract.functions {
+            let kind = match fnc.ty {
+                pt::FunctionTy::Constructor => SymbolKind::Constructor,
+                pt::FunctionTy::Modifier => SymbolKind::Method,
+                _ => SymbolKind::Function,
+            };
+
+            add_symbol(
+                function_name(fnc),
+                &function_key(fnc),
+                kind,
+                Some(&contract.name),
+                &fnc.loc,
+            );
+        }
+
+        for var in &contract.variables {
+            add_symbol(
+                &var.name,
+                &var.name,
+                SymbolKind::Field,
+                Some(&contract.name),
+                &var.loc,
+            );
+        }
+    }
+
+    for strct in &ns.structs {
+        add_symbol(
+            &strct.name,
+            &strct.name,
+            SymbolKind::Struct,
+            None,
+            &strct.loc,
+        );
+    }
+
+    for enm in &ns.enums {
+        add_symbol(&enm.name, &enm.name, SymbolKind::Enum, None, &enm.loc);
+    }
+
+    for event in &ns.events {
+        add_symbol(
+            &event.name,
+            &event.signature,
+            SymbolKind::Event,
+            None,
+            &event.loc,
+        );
+    }
+
+    let mut references = References {
+        analysis,
+        list: Vec::new(),
+    };
+
+    for contract in &ns.contracts {
+        for fnc in &contract.functions {
+            visit::walk_stmts(&mut references, &fnc.body);
+        }
+
+        for var in &contract.variables {
+            if let Some(init) = &var.initializer {
+                visit::walk_expr(&mut references, init);
+            }
+        }
+    }
+
+    for reference in references.list {
+        if let Ok(path) = reference.location.uri.to_file_path() {
+            if let Some(file) = files.get_mut(&path) {
+                file.references.push(reference);
+            }
+        }
+    }
+
+    files
+}
+
+// The name a function is known by. Constructors, fallback and receive functions have no
+// name but a keyword.
+pub fn function_name(fnc: &Function) -> &str {
+    match fnc.ty {
+        pt::FunctionTy::Constructor => "constructor",
+        pt::FunctionTy::Fallback => "fallback",
+        pt::FunctionTy::Receive => "receive",
+        pt::FunctionTy::Modifier | pt::FunctionTy::Function => fnc.name.as_str(),
+    }
+}
+
+// What a function goes by in its declaration. Constructors, fallback and receive functions
+// have a signature without a name, if any, so their keyword is added.
+fn function_key(fnc: &Function) -> String {
+    match fnc.ty {
+        pt::FunctionTy::Modifier | pt::FunctionTy::Function => fnc.signature.clone(),
+        _ => format!("{} {}", function_name(fnc), fnc.signature),
+    }
+}
+
+// The byte offsets of a name in the text a loc covers. The loc of a declaration covers all
+// of it, from the keyword it starts with, and the loc of a call includes its arguments, so
+// the name is searched for as a whole word. Without a match the name is taken to start the
+// loc.
+pub fn name_range(text: &str, loc: &pt::Loc, name: &str) -> (usize, usize) {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    let covered = text.get(loc.1..loc.2).unwrap_or_default();
+
+    let start = covered
+        .match_indices(name)
+        .map(|(start, _)| start)
+        .find(|start| {
+            let before = covered[..*start].chars().next_back();
+            let after = covered[start + name.len()..].chars().next();
+
+            !before.map_or(false, is_ident) && !after.map_or(false, is_ident)
+        });
+
+    match start {
+        Some(start) if !name.is_empty() => (loc.1 + start, loc.1 + start + name.len()),
+        _ => (loc.1, loc.1 + name.len()),
+    }
+}
+
+// The declaration at a loc in the analysis.
+fn declaration(
+    analysis: &Analysis,
+    loc: &pt::Loc,
+    container: Option<&String>,
+    key: &str,
+) -> Option<Decl> {
+    Some(Decl {
+        path: analysis.paths.get(loc.0)?.clone(),
+        container: container.cloned(),
+        name: key.to_string(),
+    })
+}
+
+// The lsp location of a name in the text a loc covers.
+fn locate(analysis: &Analysis, loc: &pt::Loc, name: &str) -> Option<Location> {
+    let line_index = analysis.line_indexes.get(loc.0)?;
+    let uri = analysis.file_uri(loc.0)?;
+
+    let (start, end) = name_range(&analysis.files[loc.0], loc, name);
+    let range = Range::new(line_index.position(start), line_index.position(end));
+
+    Some(Location::new(uri, range))
+}
+
+// Collects the uses of functions, state variables, contracts and events.
+struct References<'a> {
+    analysis: &'a Analysis,
+    list: Vec<Reference>,
+}
+
+impl<'a> References<'a> {
+    // Adds a use of the declaration at the given loc, where the name is somewhere in the
+    // used loc.
+    fn add(
+        &mut self,
+        decl: &pt::Loc,
+        container: Option<&String>,
+        key: &str,
+        name: &str,
+        used: &pt::Loc,
+    ) {
+        if let (Some(decl), Some(location)) = (
+            declaration(self.analysis, decl, container, key),
+            locate(self.analysis, used, name),
+        ) {
+            self.list.push(Reference { decl, location });
+        }
+    }
+}
+
+impl<'a> Visitor for References<'a> {
+    fn stmt(&mut self, stmt: &Statement) {
+        if let Statement::Emit {
+            event_no,
+            event_loc,
+            ..
+        } = stmt
+        {
+            let event = &self.analysis.ns.events[*event_no];
+
+            self.add(&event.loc, None, &event.signature, &event.name, event_loc);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expression) {
+        let ns = &self.analysis.ns;
+
+        match expr {
+            Expression::InternalFunctionCall {
+                loc,
+                contract_no,
+                function_no,
+                ..
+            }
+            | Expression::ExternalFunctionCall {
+                loc,
+                contract_no,
+                function_no,
+                ..
+            } => {
+                let contract = &ns.contracts[*contract_no];
+                let fnc = &contract.functions[*function_no];
+
+                self.add(
+                    &fnc.loc,
+                    Some(&contract.name),
+                    &function_key(fnc),
+                    function_name(fnc),
+                    loc,
+                );
+            }
+            Expression::StorageVariable(loc, _, contract_no, var_no) => {
+                let contract = &ns.contracts[*contract_no];
+                let var = &contract.variables[*var_no];
+
+                self.add(&var.loc, Some(&contract.name), &var.name, &var.name, loc);
+            }
+            Expression::Constructor {
+                loc, contract_no, ..
+            } => {
+                let contract = &ns.contracts[*contract_no];
+
+                self.add(&contract.loc, None, &contract.name, &contract.name, loc);
+            }
+            _ => {}
+        }
+    }
+}
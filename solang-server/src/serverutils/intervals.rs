@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+// Finds the innermost of a set of ranges of offsets which contains a given offset. The
+// ranges are cut into segments at their boundaries and the innermost range over each
+// segment is worked out up front, so a lookup is a binary search over the segments.
+#[derive(Debug)]
+pub struct IntervalIndex<T> {
+    entries: Vec<(u64, u64, T)>,
+    // Start offset of each segment. A segment ends where the next one starts; nothing
+    // covers the offsets from the last one on.
+    bounds: Vec<u64>,
+    // The innermost entry over each segment, by index into entries.
+    innermost: Vec<Option<usize>>,
+}
+
+impl<T> IntervalIndex<T> {
+    // Builds the index from ranges which include both their start and end offset. Of two
+    // ranges of the same length, the one which comes first in the list wins.
+    pub fn new(entries: Vec<(u64, u64, T)>) -> Self {
+        let entries: Vec<(u64, u64, T)> = entries
+            .into_iter()
+            .filter(|(start, end, _)| start <= end)
+            .collect();
+
+        let mut bounds: Vec<u64> = entries
+            .iter()
+            .flat_map(|(start, end, _)| vec![*start, end + 1])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut starts: Vec<usize> = (0..entries.len()).collect();
+        starts.sort_by_key(|&i| entries[i].0);
+
+        let mut ends: Vec<usize> = (0..entries.len()).collect();
+        ends.sort_by_key(|&i| entries[i].1);
+
+        let key = |i: usize| (entries[i].1 - entries[i].0, i);
+
+        // Sweep over the segments, keeping the entries which cover the current one ordered
+        // by length
+        let mut active = BTreeSet::new();
+        let mut innermost = Vec::with_capacity(bounds.len());
+        let mut next_start = 0;
+        let mut next_end = 0;
+
+        for &bound in &bounds {
+            while next_end < ends.len() && entries[ends[next_end]].1 < bound {
+                active.remove(&key(ends[next_end]));
+                next_end += 1;
+            }
+
+            while next_start < starts.len() && entries[starts[next_start]].0 <= bound {
+                active.insert(key(starts[next_start]));
+                next_start += 1;
+            }
+
+            innermost.push(active.iter().next().map(|&(_, i)| i));
+        }
+
+        IntervalIndex {
+            entries,
+            bounds,
+            innermost,
+        }
+    }
+
+    // The innermost entry which contains the offset, if any.
+    pub fn find(&self, offset: u64) -> Option<&(u64, u64, T)> {
+        let segment = match self.bounds.binary_search(&offset) {
+            Ok(segment) => segment,
+            Err(0) => return None,
+            Err(next) => next - 1,
+        };
+
+        self.innermost[segment].map(|i| &self.entries[i])
+    }
+}
+
+impl<T> Default for IntervalIndex<T> {
+    fn default() -> Self {
+        IntervalIndex {
+            entries: Vec::new(),
+            bounds: Vec::new(),
+            innermost: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(index: &IntervalIndex<&'static str>, offset: u64) -> Option<&'static str> {
+        index.find(offset).map(|(_, _, name)| *name)
+    }
+
+    #[test]
+    fn nested() {
+        let index = IntervalIndex::new(vec![
+            (0, 100, "contract"),
+            (10, 50, "function"),
+            (20, 30, "call"),
+            (22, 24, "argument"),
+        ]);
+
+        assert_eq!(find(&index, 0), Some("contract"));
+        assert_eq!(find(&index, 9), Some("contract"));
+        assert_eq!(find(&index, 10), Some("function"));
+        assert_eq!(find(&index, 21), Some("call"));
+        assert_eq!(find(&index, 22), Some("argument"));
+        assert_eq!(find(&index, 24), Some("argument"));
+        assert_eq!(find(&index, 25), Some("call"));
+        assert_eq!(find(&index, 31), Some("function"));
+        assert_eq!(find(&index, 51), Some("contract"));
+        assert_eq!(find(&index, 100), Some("contract"));
+        assert_eq!(find(&index, 101), None);
+    }
+
+    #[test]
+    fn equal_length() {
+        let index = IntervalIndex::new(vec![(5, 10, "first"), (5, 10, "second"), (8, 13, "third")]);
+
+        assert_eq!(find(&index, 5), Some("first"));
+        assert_eq!(find(&index, 9), Some("first"));
+        assert_eq!(find(&index, 11), Some("third"));
+    }
+
+    #[test]
+    fn adjacent() {
+        let index = IntervalIndex::new(vec![(10, 19, "left"), (20, 29, "right"), (40, 49, "far")]);
+
+        assert_eq!(find(&index, 9), None);
+        assert_eq!(find(&index, 19), Some("left"));
+        assert_eq!(find(&index, 20), Some("right"));
+        assert_eq!(find(&index, 29), Some("right"));
+        assert_eq!(find(&index, 30), None);
+        assert_eq!(find(&index, 39), None);
+        assert_eq!(find(&index, 45), Some("far"));
+        assert_eq!(find(&index, 50), None);
+    }
+
+    #[test]
+    fn empty() {
+        let index = IntervalIndex::new(vec![(7, 3, "backwards")]);
+
+        assert_eq!(find(&index, 5), None);
+        assert_eq!(find(&IntervalIndex::default(), 0), None);
+    }
+}
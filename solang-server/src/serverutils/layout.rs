@@ -0,0 +1,261 @@
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::config::Settings;
+
+// Files at the root of a workspace folder which tell how its sources are laid out.
+pub const LAYOUT_FILES: &[&str] = &[
+    "foundry.toml",
+    "remappings.txt",
+    "hardhat.config.js",
+    "hardhat.config.ts",
+    "hardhat.config.cjs",
+    "hardhat.config.mjs",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutKind {
+    Foundry,
+    Hardhat,
+    // Packages installed with npm, without a hardhat configuration.
+    Npm,
+    // Only a remappings.txt, as kept by projects which build with solc directly.
+    Remappings,
+}
+
+impl fmt::Display for LayoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LayoutKind::Foundry => "Foundry",
+            LayoutKind::Hardhat => "Hardhat",
+            LayoutKind::Npm => "npm",
+            LayoutKind::Remappings => "remappings.txt",
+        })
+    }
+}
+
+// The subset of foundry.toml which says where sources and libraries are.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FoundryConfig {
+    profile: HashMap<String, FoundryProfile>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FoundryProfile {
+    src: Option<PathBuf>,
+    libs: Option<Vec<PathBuf>>,
+    remappings: Vec<String>,
+}
+
+// Where the sources and libraries of a workspace folder are, derived from the build tool
+// it uses. All paths are absolute.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub kind: LayoutKind,
+    pub root: PathBuf,
+    pub sources: Vec<PathBuf>,
+    pub libs: Vec<PathBuf>,
+    pub remappings: Vec<String>,
+}
+
+impl Layout {
+    // Works out the layout of the workspace folder from the files at its root. Foundry is
+    // preferred over Hardhat when both are configured, since a hybrid project builds with
+    // forge and keeps its packages in node_modules as well.
+    pub fn detect(root: &Path) -> Option<Layout> {
+        let node_modules = root.join("node_modules");
+
+        let mut layout = if root.join("foundry.toml").is_file() {
+            Layout::foundry(root)
+        } else if let Some(config) = LAYOUT_FILES
+            .iter()
+            .filter(|name| name.starts_with("hardhat.config."))
+            .map(|name| root.join(name))
+            .find(|file| file.is_file())
+        {
+            Layout::hardhat(root, &config)
+        } else if node_modules.is_dir() {
+            Layout {
+                kind: LayoutKind::Npm,
+                root: root.to_path_buf(),
+                sources: vec![root.join("contracts")],
+                libs: Vec::new(),
+                remappings: Vec::new(),
+            }
+        } else if root.join("remappings.txt").is_file() {
+            Layout {
+                kind: LayoutKind::Remappings,
+                root: root.to_path_buf(),
+                sources: Vec::new(),
+                libs: Vec::new(),
+                remappings: Vec::new(),
+            }
+        } else {
+            return None;
+        };
+
+        if node_modules.is_dir() && !layout.libs.contains(&node_modules) {
+            layout.libs.push(node_modules);
+        }
+
+        // remappings.txt is read by forge, and by the hardhat-foundry plugin
+        if let Ok(contents) = fs::read_to_string(root.join("remappings.txt")) {
+            layout.remappings.extend(
+                contents
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.to_string()),
+            );
+        }
+
+        layout.sources.retain(|dir| dir.is_dir());
+        layout.remappings = layout
+            .remappings
+            .iter()
+            .map(|remapping| Layout::absolute(root, remapping))
+            .collect();
+
+        Some(layout)
+    }
+
+    fn foundry(root: &Path) -> Layout {
+        let mut config: FoundryConfig = fs::read_to_string(root.join("foundry.toml"))
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        let profile = config.profile.remove("default").unwrap_or_default();
+
+        let src = profile.src.unwrap_or_else(|| PathBuf::from("src"));
+        let libs: Vec<PathBuf> = profile
+            .libs
+            .unwrap_or_else(|| vec![PathBuf::from("lib")])
+            .iter()
+            .map(|lib| root.join(lib))
+            .collect();
+
+        // Like forge, map each library to its sources unless told otherwise:
+        // lib/forge-std/src is imported as "forge-std/"
+        let mut remappings = Vec::new();
+
+        for lib in &libs {
+            if let Ok(entries) = fs::read_dir(lib) {
+                for entry in entries.filter_map(|entry| entry.ok()) {
+                    let dir = entry.path();
+                    let name = entry.file_name().to_string_lossy().to_string();
+
+                    if !dir.is_dir() || name.starts_with('.') {
+                        continue;
+                    }
+
+                    let dir = if dir.join("src").is_dir() {
+                        dir.join("src")
+                    } else if dir.join("contracts").is_dir() {
+                        dir.join("contracts")
+                    } else {
+                        dir
+                    };
+
+                    remappings.push(format!("{}/={}/", name, dir.display()));
+                }
+            }
+        }
+
+        // Configured remappings take precedence over the derived ones
+        remappings.sort();
+        remappings.extend(profile.remappings);
+
+        Layout {
+            kind: LayoutKind::Foundry,
+            root: root.to_path_buf(),
+            sources: vec![root.join(src)],
+            libs,
+            remappings,
+        }
+    }
+
+    // Hardhat keeps its sources in "contracts" unless paths.sources says otherwise. The
+    // configuration is a script, so only a plain string value can be picked up.
+    fn hardhat(root: &Path, config: &Path) -> Layout {
+        let sources = fs::read_to_string(config)
+            .ok()
+            .and_then(|contents| Layout::string_property(&contents, "sources"))
+            .unwrap_or_else(|| "contracts".to_string());
+
+        Layout {
+            kind: LayoutKind::Hardhat,
+            root: root.to_path_buf(),
+            sources: vec![root.join(sources)],
+            libs: Vec::new(),
+            remappings: Vec::new(),
+        }
+    }
+
+    // The string value of "name: '...'" in a script, if there is one.
+    fn string_property(contents: &str, name: &str) -> Option<String> {
+        contents.match_indices(name).find_map(|(start, _)| {
+            let rest = contents[start + name.len()..].trim_start();
+            let rest = rest.strip_prefix(':')?.trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let end = rest[1..].find(quote)?;
+
+            Some(rest[1..end + 1].to_string())
+        })
+    }
+
+    // Makes the target of a remapping absolute, since remappings are relative to the root.
+    fn absolute(root: &Path, remapping: &str) -> String {
+        match remapping.find('=') {
+            Some(eq) => format!(
+                "{}={}",
+                &remapping[..eq],
+                root.join(&remapping[eq + 1..]).display()
+            ),
+            None => remapping.to_string(),
+        }
+    }
+
+    // Adds the source and library directories to the import paths, and the remappings.
+    pub fn apply(&self, settings: &mut Settings) {
+        for dir in self.sources.iter().chain(&self.libs) {
+            if !settings.import_paths.contains(dir) {
+                settings.import_paths.push(dir.clone());
+            }
+        }
+
+        settings.remappings.extend(self.remappings.iter().cloned());
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dirs = |dirs: &[PathBuf]| {
+            dirs.iter()
+                .map(|dir| {
+                    dir.strip_prefix(&self.root)
+                        .unwrap_or(dir)
+                        .display()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        write!(
+            f,
+            "{} project at ‘{}’: sources [{}], libraries [{}], {} remappings",
+            self.kind,
+            self.root.display(),
+            dirs(&self.sources),
+            dirs(&self.libs),
+            self.remappings.len()
+        )
+    }
+}
@@ -0,0 +1,254 @@
+use tower_lsp::lsp_types::Position;
+
+// A zero based line and column. Depending on where it came from, the column is counted
+// either in utf-8 bytes or in utf-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+// A character which takes more than one byte in utf-8, recorded so columns can be converted
+// between encodings without looking at the text again.
+#[derive(Debug, Clone, Copy)]
+struct WideChar {
+    // Byte offset of the character from the start of its line.
+    start: usize,
+    len_utf8: usize,
+    len_utf16: usize,
+}
+
+// Maps byte offsets in a file to lines and columns and back. Lines end at "\n", "\r\n" or
+// a lone "\r", as in the lsp specification. Built once per file so that every lookup is a
+// binary search over the line starts.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    // Byte offset of the start of each line.
+    line_starts: Vec<usize>,
+    // Byte offset of the end of each line, excluding the line terminator.
+    line_ends: Vec<usize>,
+    // The wide characters of each line, in order.
+    wide_chars: Vec<Vec<WideChar>>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut line_ends = Vec::new();
+        let mut wide_chars = vec![Vec::new()];
+
+        let bytes = text.as_bytes();
+
+        for (ind, c) in text.char_indices() {
+            let line_start = line_starts[line_starts.len() - 1];
+
+            match c {
+                '\n' => {
+                    let end = if ind > 0 && bytes[ind - 1] == b'\r' {
+                        ind - 1
+                    } else {
+                        ind
+                    };
+                    line_ends.push(end);
+                    line_starts.push(ind + 1);
+                    wide_chars.push(Vec::new());
+                }
+                '\r' if bytes.get(ind + 1) != Some(&b'\n') => {
+                    line_ends.push(ind);
+                    line_starts.push(ind + 1);
+                    wide_chars.push(Vec::new());
+                }
+                _ if c.len_utf8() > 1 => {
+                    let last = wide_chars.len() - 1;
+                    wide_chars[last].push(WideChar {
+                        start: ind - line_start,
+                        len_utf8: c.len_utf8(),
+                        len_utf16: c.len_utf16(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        line_ends.push(text.len());
+
+        LineIndex {
+            line_starts,
+            line_ends,
+            wide_chars,
+        }
+    }
+
+    // Line and utf-8 column of the given byte offset. Offsets past the end of the text are
+    // clamped to it.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.line_ends[self.line_ends.len() - 1]);
+
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+
+        LineCol {
+            line,
+            col: offset - self.line_starts[line],
+        }
+    }
+
+    // Byte offset of the given line and utf-8 column. Columns past the end of the line are
+    // clamped to it, before the line terminator.
+    pub fn offset(&self, line_col: LineCol) -> usize {
+        if line_col.line >= self.line_starts.len() {
+            return self.line_ends[self.line_ends.len() - 1];
+        }
+
+        let start = self.line_starts[line_col.line];
+        let end = self.line_ends[line_col.line];
+
+        (start + line_col.col).min(end)
+    }
+
+    // Converts a utf-8 column to a utf-16 column on the same line.
+    pub fn to_utf16(&self, line_col: LineCol) -> LineCol {
+        let mut col = line_col.col;
+
+        if let Some(wide_chars) = self.wide_chars.get(line_col.line) {
+            for wide in wide_chars {
+                if wide.start >= line_col.col {
+                    break;
+                }
+                col -= wide.len_utf8 - wide.len_utf16;
+            }
+        }
+
+        LineCol {
+            line: line_col.line,
+            col,
+        }
+    }
+
+    // Converts a utf-16 column to a utf-8 column on the same line. A column which falls
+    // inside a surrogate pair is moved to the start of the character.
+    pub fn to_utf8(&self, line_col: LineCol) -> LineCol {
+        let mut col = line_col.col;
+
+        if let Some(wide_chars) = self.wide_chars.get(line_col.line) {
+            // How many more bytes than code units the wide characters so far have taken
+            let mut extra = 0;
+
+            for wide in wide_chars {
+                let start_utf16 = wide.start - extra;
+
+                if start_utf16 >= line_col.col {
+                    break;
+                }
+
+                if line_col.col < start_utf16 + wide.len_utf16 {
+                    return LineCol {
+                        line: line_col.line,
+                        col: wide.start,
+                    };
+                }
+
+                extra += wide.len_utf8 - wide.len_utf16;
+            }
+
+            col += extra;
+        }
+
+        LineCol {
+            line: line_col.line,
+            col,
+        }
+    }
+
+    // The lsp position, in utf-16 code units, of the given byte offset.
+    pub fn position(&self, offset: usize) -> Position {
+        let line_col = self.to_utf16(self.line_col(offset));
+
+        Position::new(line_col.line as u64, line_col.col as u64)
+    }
+
+    // The byte offset of the given lsp position, in utf-16 code units.
+    pub fn position_to_offset(&self, pos: Position) -> usize {
+        let line_col = self.to_utf8(LineCol {
+            line: pos.line as usize,
+            col: pos.character as usize,
+        });
+
+        self.offset(line_col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_col(line: usize, col: usize) -> LineCol {
+        LineCol { line, col }
+    }
+
+    #[test]
+    fn lines_and_columns() {
+        let index = LineIndex::new("ab\ncd\n");
+
+        assert_eq!(index.line_col(0), line_col(0, 0));
+        assert_eq!(index.line_col(2), line_col(0, 2));
+        assert_eq!(index.line_col(3), line_col(1, 0));
+        assert_eq!(index.line_col(6), line_col(2, 0));
+
+        assert_eq!(index.offset(line_col(1, 1)), 4);
+        assert_eq!(index.offset(line_col(2, 0)), 6);
+    }
+
+    #[test]
+    fn line_terminators() {
+        // "\r\n" ends a line as one terminator, a lone "\r" on its own
+        let index = LineIndex::new("a\r\nb\rc");
+
+        assert_eq!(index.line_col(3), line_col(1, 0));
+        assert_eq!(index.line_col(5), line_col(2, 0));
+
+        // The end of a line is before its terminator
+        assert_eq!(index.offset(line_col(0, 1)), 1);
+        assert_eq!(index.offset(line_col(0, 2)), 1);
+        assert_eq!(index.offset(line_col(1, 5)), 4);
+    }
+
+    #[test]
+    fn clamping() {
+        let index = LineIndex::new("ab\ncd");
+
+        assert_eq!(index.line_col(100), line_col(1, 2));
+        assert_eq!(index.offset(line_col(0, 100)), 2);
+        assert_eq!(index.offset(line_col(7, 0)), 5);
+        assert_eq!(index.position_to_offset(Position::new(0, 100)), 2);
+        assert_eq!(index.position_to_offset(Position::new(7, 3)), 5);
+        assert_eq!(index.position(100), Position::new(1, 2));
+    }
+
+    #[test]
+    fn utf16_columns() {
+        // "é" takes two bytes and one code unit, "😀" four bytes and a surrogate pair
+        let index = LineIndex::new("é😀b\n😀");
+
+        assert_eq!(index.position(2), Position::new(0, 1));
+        assert_eq!(index.position(6), Position::new(0, 3));
+        assert_eq!(index.position(7), Position::new(0, 4));
+        assert_eq!(index.position(12), Position::new(1, 2));
+
+        assert_eq!(index.position_to_offset(Position::new(0, 1)), 2);
+        assert_eq!(index.position_to_offset(Position::new(0, 3)), 6);
+        assert_eq!(index.position_to_offset(Position::new(0, 4)), 7);
+        assert_eq!(index.position_to_offset(Position::new(1, 2)), 12);
+    }
+
+    #[test]
+    fn inside_surrogate_pair() {
+        let index = LineIndex::new("a😀b");
+
+        // Halfway through the pair is taken as the start of the character
+        assert_eq!(index.position_to_offset(Position::new(0, 2)), 1);
+        assert_eq!(index.position_to_offset(Position::new(0, 3)), 5);
+    }
+}
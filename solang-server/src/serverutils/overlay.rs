@@ -0,0 +1,60 @@
+use solang::file_cache::FileCache;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::documents::DocumentStore;
+
+// A snapshot of the text of every open document, layered in front of the files on disk so
+// that imports resolve against unsaved editor buffers.
+#[derive(Debug, Default, Clone)]
+pub struct Overlay {
+    files: HashMap<PathBuf, String>,
+}
+
+impl Overlay {
+    pub fn new(documents: &DocumentStore) -> Self {
+        let files = documents
+            .snapshot()
+            .into_iter()
+            .filter_map(|(uri, doc)| {
+                let path = uri.to_file_path().ok()?;
+                Some((Overlay::canonical(&path), doc.text))
+            })
+            .collect();
+
+        Overlay { files }
+    }
+
+    // Open documents are keyed by their canonical path, so that a file is found however the
+    // path to it is spelled. Files which do not exist on disk keep their path.
+    pub fn canonical(path: &Path) -> PathBuf {
+        path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    // The text of a file: the open document if there is one, else the file on disk.
+    pub fn read(&self, path: &Path) -> Option<String> {
+        match self.files.get(&Overlay::canonical(path)) {
+            Some(text) => Some(text.clone()),
+            None => fs::read_to_string(path).ok(),
+        }
+    }
+
+    // Creates a file cache which serves the open documents under their path, which is the
+    // name the analysed document itself is read by. Solang looks up imported files by the
+    // import path as written rather than by where they are on disk, so imported documents
+    // only come from the editor once ImportResolver::populate has added them under that
+    // name. Any other file is read from disk by the cache as usual.
+    pub fn file_cache(&self) -> FileCache {
+        let mut filecache = FileCache::new();
+
+        for (path, text) in &self.files {
+            if let Some(filename) = path.to_str() {
+                filecache.set_file_contents(filename, text.clone());
+            }
+        }
+
+        filecache
+    }
+}
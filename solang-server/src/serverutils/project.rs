@@ -0,0 +1,136 @@
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::config::Settings;
+use super::diagnostics::SeverityOverride;
+use super::imports::Remapping;
+
+// Names of the project files, in order of preference when a directory has both.
+pub const PROJECT_FILES: &[&str] = &["solang.toml", ".solang.json"];
+
+// Contents of a project file. Paths are relative to the directory of the file.
+//
+//     target = "substrate"
+//     targets = ["substrate", "ewasm"]
+//     import_paths = ["contracts", "lib"]
+//     remappings = ["@openzeppelin/=node_modules/@openzeppelin/"]
+//     exclude = ["test/**"]
+//
+//     [diagnostics]
+//     unreachable = "off"
+//
+//     [lint]
+//     debug_hints = false
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    pub target: Option<String>,
+    pub targets: Option<Vec<String>>,
+    #[serde(alias = "importPaths")]
+    pub import_paths: Vec<PathBuf>,
+    pub remappings: Vec<String>,
+    pub exclude: Vec<String>,
+    pub diagnostics: HashMap<String, SeverityOverride>,
+    pub lint: LintConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    #[serde(alias = "debugHints")]
+    pub debug_hints: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    // The directory of the project file.
+    pub root: PathBuf,
+    pub config: ProjectConfig,
+}
+
+impl Project {
+    // Looks for a project file in the given directory and each of its parents, and loads
+    // the first one found. Returns an error if that file cannot be read or parsed.
+    pub fn discover(dir: &Path) -> Option<Result<Project, String>> {
+        for ancestor in dir.ancestors() {
+            for name in PROJECT_FILES {
+                let file = ancestor.join(name);
+
+                if file.is_file() {
+                    return Some(Project::load(&file));
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn load(file: &Path) -> Result<Project, String> {
+        let contents = fs::read_to_string(file)
+            .map_err(|err| format!("cannot read ‘{}’: {}", file.display(), err))?;
+
+        let config = if file.extension().map_or(false, |ext| ext == "toml") {
+            toml::from_str(&contents).map_err(|err| err.to_string())
+        } else {
+            serde_json::from_str(&contents).map_err(|err| err.to_string())
+        }
+        .map_err(|err| format!("invalid project file ‘{}’: {}", file.display(), err))?;
+
+        Ok(Project {
+            root: file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            config,
+        })
+    }
+
+    // Is this one of the files a project can be configured with.
+    pub fn is_project_file(path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| PROJECT_FILES.contains(&name))
+    }
+
+    // Layers the project configuration over the editor settings. Anything the project sets
+    // takes precedence.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(target) = &self.config.target {
+            settings.target = target.clone();
+            settings.targets.clear();
+        }
+
+        if let Some(targets) = &self.config.targets {
+            settings.targets = targets.clone();
+        }
+
+        for path in &self.config.import_paths {
+            settings.import_paths.push(self.root.join(path));
+        }
+
+        for remapping in &self.config.remappings {
+            let remapping = match Remapping::parse(remapping, &self.root) {
+                Some(r) => format!("{}={}", r.prefix, r.target.display()),
+                None => remapping.clone(),
+            };
+
+            settings.remappings.push(remapping);
+        }
+
+        for pattern in &self.config.exclude {
+            settings.exclude.push(if pattern.starts_with("**") {
+                pattern.clone()
+            } else {
+                self.root.join(pattern).to_string_lossy().to_string()
+            });
+        }
+
+        for (code, severity) in &self.config.diagnostics {
+            settings.diagnostics.insert(code.clone(), *severity);
+        }
+
+        if let Some(debug_hints) = self.config.lint.debug_hints {
+            settings.debug_hints = debug_hints;
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use super::comments::{directive, line_comments};
+use super::diagnostics;
+use super::line_index::LineIndex;
+
+// Which lines a suppression comment applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scope {
+    Line(usize),
+    File,
+}
+
+// A "solang-disable" comment found in a file.
+#[derive(Debug)]
+struct Suppression {
+    scope: Scope,
+    // The codes which are suppressed; all of them if empty.
+    codes: Vec<String>,
+    // Byte offsets of the comment, to report problems with it.
+    start: usize,
+    end: usize,
+    used: bool,
+}
+
+// A problem with a suppression comment, to be reported as a diagnostic in its file.
+pub struct Problem {
+    pub code: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+// The suppression comments of a file. Supported are:
+//
+//     // solang-disable-next-line [code, ...]
+//     // solang-disable-line [code, ...]
+//     // solang-disable [code, ...]
+//
+// The first two apply to the line below and the line of the comment, the last one to the
+// whole file. Without any codes every diagnostic is suppressed.
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    list: Vec<Suppression>,
+}
+
+impl Suppressions {
+    pub fn new(text: &str, line_index: &LineIndex) -> Self {
+        let mut list = Vec::new();
+
+        for (start, end) in line_comments(text) {
+            let comment = &text[start..end];
+
+            let (scope, rest) = if let Some(rest) = directive(comment, "solang-disable-next-line") {
+                (Scope::Line(line_index.line_col(start).line + 1), rest)
+            } else if let Some(rest) = directive(comment, "solang-disable-line") {
+                (Scope::Line(line_index.line_col(start).line), rest)
+            } else if let Some(rest) = directive(comment, "solang-disable") {
+                (Scope::File, rest)
+            } else {
+                continue;
+            };
+
+            let codes = rest
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|code| !code.is_empty())
+                .map(|code| code.to_string())
+                .collect();
+
+            list.push(Suppression {
+                scope,
+                codes,
+                start,
+                end,
+                used: false,
+            });
+        }
+
+        Suppressions { list }
+    }
+
+    // Is a diagnostic with the given code starting on the given line suppressed. Every
+    // suppression which matches is marked as used.
+    pub fn suppresses(&mut self, line: usize, code: &str) -> bool {
+        let mut suppressed = false;
+
+        for suppression in &mut self.list {
+            let in_scope = match suppression.scope {
+                Scope::Line(l) => l == line,
+                Scope::File => true,
+            };
+
+            if in_scope
+                && (suppression.codes.is_empty() || suppression.codes.iter().any(|c| c == code))
+            {
+                suppression.used = true;
+                suppressed = true;
+            }
+        }
+
+        suppressed
+    }
+
+    // Suppressions naming codes which do not exist, and suppressions which did not suppress
+    // anything.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        for suppression in &self.list {
+            let unknown: Vec<&str> = suppression
+                .codes
+                .iter()
+                .map(|code| code.as_str())
+                .filter(|code| !diagnostics::is_known(code))
+                .collect();
+
+            if !unknown.is_empty() {
+                problems.push(Problem {
+                    code: "unknown-suppression",
+                    start: suppression.start,
+                    end: suppression.end,
+                    message: format!("unknown diagnostic code ‘{}’", unknown.join("’, ‘")),
+                });
+            } else if !suppression.used {
+                problems.push(Problem {
+                    code: "unused-suppression",
+                    start: suppression.start,
+                    end: suppression.end,
+                    message: "suppression comment does not suppress any diagnostic".to_string(),
+                });
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suppressions(text: &str) -> Suppressions {
+        Suppressions::new(text, &LineIndex::new(text))
+    }
+
+    #[test]
+    fn scopes() {
+        let mut file = suppressions(
+            "// solang-disable-next-line unused-variable\n\
+             uint a;\n\
+             uint b; // solang-disable-line deprecated, unreachable\n",
+        );
+
+        assert!(file.suppresses(1, "unused-variable"));
+        assert!(!file.suppresses(0, "unused-variable"));
+        assert!(!file.suppresses(1, "deprecated"));
+        assert!(file.suppresses(2, "deprecated"));
+        assert!(file.suppresses(2, "unreachable"));
+
+        let mut file = suppressions("// solang-disable\nuint a;\n");
+
+        assert!(file.suppresses(1, "unused-variable"));
+        assert!(file.suppresses(7, "deprecated"));
+    }
+
+    #[test]
+    fn not_a_directive() {
+        let mut file = suppressions(
+            "// solang-disabled unused-variable\n\
+             string s = \"// solang-disable\";\n\
+             /* solang-disable */\n",
+        );
+
+        assert!(!file.suppresses(1, "unused-variable"));
+        assert!(file.problems().is_empty());
+    }
+
+    #[test]
+    fn problems() {
+        let mut file = suppressions(
+            "// solang-disable-next-line unused-variable\n\
+             uint a;\n\
+             // solang-disable-next-line no-such-code\n\
+             uint b;\n",
+        );
+
+        file.suppresses(3, "unused-variable");
+
+        let problems = file.problems();
+
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].code, "unused-suppression");
+        assert_eq!(problems[0].start, 0);
+        assert_eq!(problems[1].code, "unknown-suppression");
+        assert_eq!(
+            problems[1].message,
+            "unknown diagnostic code ‘no-such-code’"
+        );
+    }
+}
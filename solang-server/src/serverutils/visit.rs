@@ -0,0 +1,242 @@
+use solang::sema::ast::{DestructureField, Expression, Statement, StringLocation};
+
+// Called for every statement and expression of a function body by walk_stmt and walk_expr,
+// outer nodes before the nodes inside them.
+pub trait Visitor {
+    fn stmt(&mut self, _stmt: &Statement) {}
+
+    fn expr(&mut self, _expr: &Expression) {}
+}
+
+pub fn walk_stmts(visitor: &mut impl Visitor, stmts: &[Statement]) {
+    for stmt in stmts {
+        walk_stmt(visitor, stmt);
+    }
+}
+
+pub fn walk_stmt(visitor: &mut impl Visitor, stmt: &Statement) {
+    visitor.stmt(stmt);
+
+    match stmt {
+        Statement::VariableDecl(_, _, _, expr) => {
+            if let Some(expr) = expr {
+                walk_expr(visitor, expr);
+            }
+        }
+        Statement::If(_, _, expr, then, otherwise) => {
+            walk_expr(visitor, expr);
+            walk_stmts(visitor, then);
+            walk_stmts(visitor, otherwise);
+        }
+        Statement::While(_, _, expr, body) => {
+            walk_expr(visitor, expr);
+            walk_stmts(visitor, body);
+        }
+        Statement::For {
+            init,
+            cond,
+            next,
+            body,
+            ..
+        } => {
+            walk_stmts(visitor, init);
+            if let Some(cond) = cond {
+                walk_expr(visitor, cond);
+            }
+            walk_stmts(visitor, next);
+            walk_stmts(visitor, body);
+        }
+        Statement::DoWhile(_, _, body, expr) => {
+            walk_stmts(visitor, body);
+            walk_expr(visitor, expr);
+        }
+        Statement::Expression(_, _, expr) | Statement::Delete(_, _, expr) => {
+            walk_expr(visitor, expr);
+        }
+        Statement::Destructure(_, fields, expr) => {
+            for field in fields {
+                if let DestructureField::Expression(expr) = field {
+                    walk_expr(visitor, expr);
+                }
+            }
+            walk_expr(visitor, expr);
+        }
+        Statement::Return(_, exprs) => {
+            for expr in exprs {
+                walk_expr(visitor, expr);
+            }
+        }
+        Statement::Emit { args, .. } => {
+            for arg in args {
+                walk_expr(visitor, arg);
+            }
+        }
+        Statement::TryCatch {
+            expr,
+            ok_stmt,
+            error,
+            catch_stmt,
+            ..
+        } => {
+            walk_expr(visitor, expr);
+            walk_stmts(visitor, ok_stmt);
+            if let Some(error) = error {
+                walk_stmts(visitor, &error.2);
+            }
+            walk_stmts(visitor, catch_stmt);
+        }
+        Statement::Continue(_) | Statement::Break(_) | Statement::Underscore(_) => {}
+    }
+}
+
+pub fn walk_expr(visitor: &mut impl Visitor, expr: &Expression) {
+    visitor.expr(expr);
+
+    match expr {
+        Expression::StructLiteral(_, _, exprs)
+        | Expression::ArrayLiteral(_, _, _, exprs)
+        | Expression::ConstArrayLiteral(_, _, _, exprs)
+        | Expression::Keccak256(_, _, exprs)
+        | Expression::Builtin(_, _, _, exprs)
+        | Expression::List(_, exprs) => {
+            for expr in exprs {
+                walk_expr(visitor, expr);
+            }
+        }
+
+        Expression::Add(_, _, left, right)
+        | Expression::Subtract(_, _, left, right)
+        | Expression::Multiply(_, _, left, right)
+        | Expression::UDivide(_, _, left, right)
+        | Expression::SDivide(_, _, left, right)
+        | Expression::UModulo(_, _, left, right)
+        | Expression::SModulo(_, _, left, right)
+        | Expression::Power(_, _, left, right)
+        | Expression::BitwiseOr(_, _, left, right)
+        | Expression::BitwiseAnd(_, _, left, right)
+        | Expression::BitwiseXor(_, _, left, right)
+        | Expression::ShiftLeft(_, _, left, right)
+        | Expression::ShiftRight(_, _, left, right, _)
+        | Expression::Assign(_, _, left, right)
+        | Expression::ArraySubscript(_, _, left, right)
+        | Expression::DynamicArraySubscript(_, _, left, right)
+        | Expression::DynamicArrayPush(_, left, _, right)
+        | Expression::UMore(_, left, right)
+        | Expression::ULess(_, left, right)
+        | Expression::UMoreEqual(_, left, right)
+        | Expression::ULessEqual(_, left, right)
+        | Expression::SMore(_, left, right)
+        | Expression::SLess(_, left, right)
+        | Expression::SMoreEqual(_, left, right)
+        | Expression::SLessEqual(_, left, right)
+        | Expression::Equal(_, left, right)
+        | Expression::NotEqual(_, left, right)
+        | Expression::StorageBytesSubscript(_, left, right)
+        | Expression::StorageBytesPush(_, left, right)
+        | Expression::Or(_, left, right)
+        | Expression::And(_, left, right) => {
+            walk_expr(visitor, left);
+            walk_expr(visitor, right);
+        }
+
+        Expression::Load(_, _, expr)
+        | Expression::StorageLoad(_, _, expr)
+        | Expression::ZeroExt(_, _, expr)
+        | Expression::SignExt(_, _, expr)
+        | Expression::Trunc(_, _, expr)
+        | Expression::Cast(_, _, expr)
+        | Expression::BytesCast(_, _, _, expr)
+        | Expression::PreIncrement(_, _, expr)
+        | Expression::PreDecrement(_, _, expr)
+        | Expression::PostIncrement(_, _, expr)
+        | Expression::PostDecrement(_, _, expr)
+        | Expression::Not(_, expr)
+        | Expression::Complement(_, _, expr)
+        | Expression::UnaryMinus(_, _, expr)
+        | Expression::StructMember(_, _, expr, _)
+        | Expression::AllocDynamicArray(_, _, expr, _)
+        | Expression::DynamicArrayLength(_, expr)
+        | Expression::DynamicArrayPop(_, expr, _)
+        | Expression::StorageBytesPop(_, expr)
+        | Expression::StorageBytesLength(_, expr)
+        | Expression::Balance(_, _, expr) => {
+            walk_expr(visitor, expr);
+        }
+
+        Expression::Ternary(_, _, cond, left, right) => {
+            walk_expr(visitor, cond);
+            walk_expr(visitor, left);
+            walk_expr(visitor, right);
+        }
+
+        Expression::StringCompare(_, left, right) | Expression::StringConcat(_, _, left, right) => {
+            for string in &[left, right] {
+                if let StringLocation::RunTime(expr) = string {
+                    walk_expr(visitor, expr);
+                }
+            }
+        }
+
+        Expression::InternalFunctionCall { args, .. } => {
+            for arg in args {
+                walk_expr(visitor, arg);
+            }
+        }
+        Expression::ExternalFunctionCall {
+            address,
+            args,
+            value,
+            gas,
+            ..
+        } => {
+            walk_expr(visitor, address);
+            for arg in args {
+                walk_expr(visitor, arg);
+            }
+            walk_expr(visitor, value);
+            walk_expr(visitor, gas);
+        }
+        Expression::ExternalFunctionCallRaw {
+            address,
+            args,
+            value,
+            gas,
+            ..
+        } => {
+            walk_expr(visitor, address);
+            walk_expr(visitor, args);
+            walk_expr(visitor, value);
+            walk_expr(visitor, gas);
+        }
+        Expression::Constructor {
+            args,
+            gas,
+            value,
+            salt,
+            ..
+        } => {
+            for arg in args {
+                walk_expr(visitor, arg);
+            }
+            walk_expr(visitor, gas);
+            if let Some(value) = value {
+                walk_expr(visitor, value);
+            }
+            if let Some(salt) = salt {
+                walk_expr(visitor, salt);
+            }
+        }
+
+        Expression::FunctionArg(..)
+        | Expression::BoolLiteral(..)
+        | Expression::BytesLiteral(..)
+        | Expression::CodeLiteral(..)
+        | Expression::NumberLiteral(..)
+        | Expression::Variable(..)
+        | Expression::ConstantVariable(..)
+        | Expression::StorageVariable(..)
+        | Expression::ReturnData(..)
+        | Expression::GetAddress(..)
+        | Expression::Poison => {}
+    }
+}
@@ -71,7 +71,11 @@ export function activate(context: vscode.ExtensionContext) {
 		documentSelector: [
 			{ language: 'solidity', scheme: 'file' },
 			{ language: 'solidity', scheme: 'untitled' },
-		]
+		],
+		initializationOptions: workspace.getConfiguration('solidity'),
+		synchronize: {
+			configurationSection: 'solidity'
+		}
 	};
 
 	const init: InitializeParams = {
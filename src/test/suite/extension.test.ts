@@ -76,7 +76,7 @@ async function testhover(docUri: vscode.Uri){
 
 	let contentarr1 = actualhover[0].contents as vscode.MarkdownString[];
 
-	assert.equal( contentarr1[0].value, '(mapping(address => uint256))');
+	assert.equal( contentarr1[0].value, '```solidity\nmapping(address => uint256)\n```');
 
 	var pos2 = new vscode.Position(78, 19);
 
@@ -84,7 +84,7 @@ async function testhover(docUri: vscode.Uri){
 
 	let contentarr2 = actualhover2[0].contents as vscode.MarkdownString[];
 
-	assert.equal( contentarr2[0].value, ' event HighestBidIncreased ( address bidder, \n\n uint256 amount, \n\n )');
+	assert.equal( contentarr2[0].value, '```solidity\nevent HighestBidIncreased(address bidder, uint256 amount)\n```\n\nSignature: `HighestBidIncreased(address,uint256)`  \nTopic: `0xf4757a49b326036464bec6fe419a4ae38c8a02ce3e68bf0809674f6aab8ad300`');
 
 	var pos3 = new vscode.Position(53, 13);
 
@@ -92,7 +92,7 @@ async function testhover(docUri: vscode.Uri){
 
 	let contentarr3 = actualhover3[0].contents as vscode.MarkdownString[];
 
-	assert.equal( contentarr3[0].value, '[built-in]  void require (bool): Abort execution if argument evaulates to false');
+	assert.equal( contentarr3[0].value, '```solidity\n// built-in\nrequire(bool)\n```\n\nAbort execution if argument evaulates to false');
 }
 
 async function testdiagnos(docUri: vscode.Uri, expecteddiag: vscode.Diagnostic[]){